@@ -1,8 +1,81 @@
+use super::run::ExitStatus;
 use crate::lang::ExecutableConfig;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
+#[derive(Clone)]
 pub struct Config {
+    pub command: Command,
     pub present_flag_names: HashSet<String>,
+    /// Paths of the source files, where `-` stands for stdin.
+    pub input_paths: Vec<String>,
+    pub format: Format,
+    /// An atom, given in Seaso syntax, whose truth value is output.
+    pub query: Option<String>,
+    /// Inference is abandoned once more atoms than this are inferred.
+    pub max_atoms: Option<usize>,
+    /// How deeply explanations recurse.
+    pub depth: usize,
+    /// A graph output instead of the usual output.
+    pub dot: Option<DotGraph>,
+    /// Levels of lints set explicitly with `--allow`, `--warn`, or `--deny`.
+    pub lint_levels: HashMap<Lint, LintLevel>,
+}
+
+/// What the executor does with the given program.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum Command {
+    /// Check the program, and output its denotation.
+    #[default]
+    Run,
+    /// Check the program, outputting only errors and warnings.
+    Check,
+    /// Output each source file in canonical form.
+    Fmt,
+    /// Output how the queried atom (or each truth) was inferred.
+    Explain,
+    /// Run each source file (and each in given directories), reporting which fail.
+    Test,
+    /// Read statements and commands interactively, outputting how the denotation changes.
+    Repl,
+}
+
+/// How the denotation (and warnings) are output.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+/// A graph output in the Graphviz DOT language.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DotGraph {
+    /// The proof of the queried atom.
+    Proof,
+    /// The parts, which parts they use, and which seals they break.
+    Parts,
+    /// How domains depend on each other through rules.
+    Domains,
+}
+
+/// A category of warnings, whose level is configurable.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Lint {
+    SealBreaks,
+    UsedUndeclared,
+    DependedUndefined,
+    UnboundedValues,
+}
+
+/// How the warnings of a lint are treated.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LintLevel {
+    /// Not reported.
+    Allow,
+    /// Reported as warnings.
+    Warn,
+    /// Reported as errors, failing the run.
+    Deny,
 }
 
 impl Config {
@@ -17,55 +90,212 @@ static FLAG_DESC_SLICE: &[(&str, &str)] = &[
     ("ast1", "print abstract syntax tree before preprocessing"),
     ("ast2", "print abstract syntax tree after preprocessing"),
     ("cluster", "denotation atoms are shown clustered by domain"),
+    ("deny-warnings", "deny all lints not explicitly given another level"),
     ("eq", "print domain equivalence classes and their representative members"),
+    ("fail-on-emissions", "exit with a failure status if any atoms are emitted"),
+    ("fail-on-warnings", "exit with a failure status if there are any warnings"),
     ("how", "print the concrete rule antecedents of each truth"),
     ("ir", "print the internal representation (used to compute the denotation)"),
     ("local", "implicitly localize ('namespace') domains to their parts"),
     ("no-deno", "do not print the program denotation, i.e., truths and unknowns"),
     ("source", "print given Seaso source code after preprocessing"),
     ("save", "preprocess rules s.t. they are safe by adding consequent-only variables as positive antecedents"),
+    ("strata", "print the stratum of each domain, or a cycle through negation if there are none"),
+    ("strict", "same as `--deny-warnings`"),
     ("sub", "rules implicitly infer all consequents' subconsequents"),
 ];
 
+static COMMAND_DESC_SLICE: &[(&str, Command, &str)] = &[
+    ("run", Command::Run, "check the program, and print its denotation (default)"),
+    ("check", Command::Check, "check the program, printing only errors and warnings"),
+    ("fmt", Command::Fmt, "print each source file in canonical form"),
+    ("explain", Command::Explain, "print how the queried atom (or each truth) was inferred"),
+    ("test", Command::Test, "run each source file (or those in directories), reporting failures"),
+    ("repl", Command::Repl, "enter statements interactively, printing how the denotation changes"),
+];
+static OPTION_DESC_SLICE: &[(&str, &str, &str)] = &[
+    ("format", "F", "output format: `text` (default) or `json`"),
+    ("query", "ATOM", "print the truth value of the given atom"),
+    ("max-atoms", "N", "abandon inference once more than N atoms are inferred"),
+    ("depth", "N", "explain why atoms are not true up to N levels deep (default 2)"),
+    (
+        "dot",
+        "GRAPH",
+        "print a graph in the DOT language: `proof` (of the queried atom), `parts`, or `domains`",
+    ),
+    ("allow", "LINT", "do not report warnings of the given lint"),
+    ("warn", "LINT", "report warnings of the given lint (default)"),
+    ("deny", "LINT", "report warnings of the given lint as errors, failing the run"),
+];
+static LINT_DESC_SLICE: &[(&str, Lint, &str)] = &[
+    ("seal-breaks", Lint::SealBreaks, "parts modifying domains that other parts sealed"),
+    ("used-undeclared", Lint::UsedUndeclared, "domains used but never declared or defined"),
+    ("depended-undefined", Lint::DependedUndefined, "parts using parts that are never defined"),
+    (
+        "unbounded-values",
+        Lint::UnboundedValues,
+        "rules computing values in domains that depend on themselves",
+    ),
+];
+
+impl Lint {
+    pub fn name(self) -> &'static str {
+        LINT_DESC_SLICE.iter().find(|(_, lint, _)| *lint == self).expect("all lints described").0
+    }
+}
+
 impl Config {
     pub fn known_flag_name(s: &str) -> bool {
         FLAG_DESC_SLICE.iter().any(|(key, _)| key == &s)
     }
     pub fn no_flags() -> Self {
-        Self { present_flag_names: Default::default() }
+        Self {
+            command: Command::Run,
+            present_flag_names: Default::default(),
+            input_paths: vec![],
+            format: Format::Text,
+            query: None,
+            max_atoms: None,
+            depth: 2,
+            dot: None,
+            lint_levels: Default::default(),
+        }
     }
-    pub fn from_sys_args() -> Self {
-        if std::env::args().find(|s| s == "--help").is_some() {
-            println!("Seaso executor help information. Flags:");
-            println!(" --{: <9}  {}", "help", "print this");
-            for (name, desc) in FLAG_DESC_SLICE {
-                println!(" --{: <9}  {}", name, desc);
-            }
-            std::process::exit(0);
+    pub fn print_help() {
+        println!("Seaso executor help information.");
+        println!("Usage: seaso [COMMAND] [OPTIONS] [FILES]");
+        println!("Source is read from stdin if no files are given, or for file `-`.");
+        println!("Commands:");
+        for (name, _, desc) in COMMAND_DESC_SLICE {
+            println!("  {: <18}  {}", name, desc);
+        }
+        println!("Options:");
+        println!(" --{: <18}  print this", "help");
+        for (name, value, desc) in OPTION_DESC_SLICE {
+            println!(" --{: <18}  {}", format!("{} {}", name, value), desc);
+        }
+        println!("Flags:");
+        for (name, desc) in FLAG_DESC_SLICE {
+            println!(" --{: <18}  {}", name, desc);
         }
-        let present_flag_names = std::env::args()
-            .skip(1)
-            .filter_map(|mut s| {
-                if s == "--" {
-                    None
-                } else if s.starts_with("--") {
-                    s.replace_range(0.."--".len(), "");
-                    if !Self::known_flag_name(&s) {
-                        println!("~ ~ WARNING: unrecognized flag  `{}` ~ ~", s);
+        println!("Lints:");
+        for (name, _, desc) in LINT_DESC_SLICE {
+            println!("  {: <18}  {}", name, desc);
+        }
+        println!("Exit statuses:");
+        for status in ExitStatus::ALL {
+            println!("  {: <18}  {}", status.code(), status.description());
+        }
+    }
+    /// Parses the command line arguments (excluding the executable name).
+    /// The command, if given, must precede all files. Options take values as
+    /// `--option value` or `--option=value`. Arguments after `--` are all files.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self::no_flags();
+        let mut args = args.into_iter();
+        let mut only_files = false;
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--").filter(|_| !only_files) else {
+                let command = COMMAND_DESC_SLICE.iter().find(|(name, ..)| name == &arg);
+                match command {
+                    Some((_, command, _)) if config.input_paths.is_empty() && !only_files => {
+                        config.command = *command
                     }
-                    Some(s)
-                } else {
-                    println!("~ ~ WARNING: unrecognized input `{}` ~ ~", s);
-                    None
+                    _ => config.input_paths.push(arg),
+                }
+                continue;
+            };
+            let (name, inline_value) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (name, None),
+            };
+            if name.is_empty() {
+                only_files = true;
+            } else if OPTION_DESC_SLICE.iter().any(|(key, ..)| key == &name) {
+                let value = inline_value
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("option `--{}` requires a value", name))?;
+                config.set_option(name, value)?;
+            } else if Self::known_flag_name(name) && inline_value.is_none() {
+                config.present_flag_names.insert(name.to_owned());
+            } else {
+                return Err(format!("unrecognized argument `{}`", arg));
+            }
+        }
+        if config.dot == Some(DotGraph::Proof) && config.query.is_none() {
+            return Err("`--dot proof` requires `--query`".into());
+        }
+        Ok(config)
+    }
+    fn set_option(&mut self, name: &str, value: String) -> Result<(), String> {
+        match name {
+            "format" => {
+                self.format = match value.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    _ => return Err(format!("unrecognized format `{}`", value)),
+                }
+            }
+            "query" => self.query = Some(value),
+            "max-atoms" => {
+                let n = value.parse().map_err(|_| format!("`{}` is not a number", value))?;
+                self.max_atoms = Some(n)
+            }
+            "depth" => {
+                self.depth = value.parse().map_err(|_| format!("`{}` is not a number", value))?
+            }
+            "dot" => {
+                self.dot = match value.as_str() {
+                    "proof" => Some(DotGraph::Proof),
+                    "parts" => Some(DotGraph::Parts),
+                    "domains" => Some(DotGraph::Domains),
+                    _ => return Err(format!("unrecognized graph `{}`", value)),
                 }
-            })
-            .collect();
-        Self { present_flag_names }
+            }
+            "allow" | "warn" | "deny" => {
+                let level = match name {
+                    "allow" => LintLevel::Allow,
+                    "warn" => LintLevel::Warn,
+                    _ => LintLevel::Deny,
+                };
+                let lint = LINT_DESC_SLICE
+                    .iter()
+                    .find(|(lint_name, ..)| lint_name == &value)
+                    .ok_or_else(|| format!("unrecognized lint `{}`", value))?
+                    .1;
+                self.lint_levels.insert(lint, level);
+            }
+            _ => unreachable!("options are in OPTION_DESC_SLICE"),
+        }
+        Ok(())
+    }
+    pub fn from_sys_args() -> Self {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if args.iter().any(|s| s == "--help" || s == "-h") {
+            Self::print_help();
+            std::process::exit(ExitStatus::Success.code());
+        }
+        match Self::from_args(args) {
+            Ok(config) => config,
+            Err(msg) => {
+                eprintln!("error: {}", msg);
+                eprintln!("Run with `--help` for usage information.");
+                std::process::exit(ExitStatus::UsageError.code());
+            }
+        }
     }
 }
 
 impl Config {
+    /// The explicitly given level of the lint, or else `Deny` in strict mode, or else `Warn`.
+    pub fn lint_level(&self, lint: Lint) -> LintLevel {
+        match self.lint_levels.get(&lint) {
+            Some(level) => *level,
+            None if self.test("deny-warnings") || self.test("strict") => LintLevel::Deny,
+            None => LintLevel::Warn,
+        }
+    }
     pub fn executable_config(&self) -> ExecutableConfig {
-        ExecutableConfig { subconsequence: self.test("sub") }
+        ExecutableConfig { subconsequence: self.test("sub"), max_atoms: self.max_atoms }
     }
 }
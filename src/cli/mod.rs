@@ -1,2 +1,3 @@
 pub mod config;
+pub mod repl;
 pub mod run;
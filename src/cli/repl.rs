@@ -0,0 +1,279 @@
+use super::{
+    config::{Command, Config, Format},
+    run::{run_check, ExitStatus, Failure, Outcome},
+};
+use crate::lang::{
+    dynamics::{Atom, Denotation, Knowledge},
+    load::Sources,
+    util::sorted_vec,
+    *,
+};
+use std::{
+    collections::HashSet,
+    io::{BufRead, Write},
+    path::PathBuf,
+};
+
+/// The state of a REPL session: the program entered so far, and what it denotes.
+struct Repl {
+    /// Configures each run of the program. Its command and query are ignored.
+    config: Config,
+    entries: Vec<Entry>,
+    /// Counts entries ever added (including those undone), to give each a distinct name.
+    entries_added: usize,
+    denotation: Denotation<Knowledge>,
+    /// Rendered warnings of the current program, so that only new ones are printed.
+    warnings: HashSet<String>,
+}
+
+/// A piece of the program, entered by the user.
+enum Entry {
+    Text { name: String, text: String },
+    File { path: PathBuf },
+}
+
+static REPL_COMMAND_DESC_SLICE: &[(&str, &str)] = &[
+    (":truths", "print the truths"),
+    (":unknowns", "print the unknowns"),
+    (":emissions", "print the emissions"),
+    (":why ATOM", "print how the given atom was inferred, or its truth value"),
+    (":undo", "remove the last entry"),
+    (":load FILE", "add the program of the given file (and its imports)"),
+    (":help", "print this"),
+    (":quit", "end the session"),
+];
+
+/////////////////////////
+
+/// Reads statements, parts, and commands from `input` until it ends, or until `:quit`.
+/// After each entry, the program is checked and run again, and the changes to its denotation are
+/// printed. An entry ends with a complete statement or part, or with an empty line.
+/// Entries rejected by parsing or static checks are discarded. Queries (e.g., `?- a(X).`) are
+/// answered, but not kept. Each file at the input
+/// paths of `config` is loaded first.
+pub fn run_repl(
+    config: &Config,
+    input: impl BufRead,
+    w: &mut impl Write,
+    e: &mut impl Write,
+) -> ExitStatus {
+    let mut repl = Repl {
+        config: config.clone(),
+        entries: vec![],
+        entries_added: 0,
+        denotation: empty_denotation(),
+        warnings: Default::default(),
+    };
+    for path in config.input_paths.iter() {
+        repl.push(Entry::File { path: path.into() }, w, e);
+    }
+    let _ = writeln!(w, "Seaso REPL. Enter statements and parts, or `:help` for commands.");
+    let mut buffer = String::new();
+    let mut lines = input.lines();
+    loop {
+        let _ = write!(w, "{}", if buffer.is_empty() { "seaso> " } else { "   ... " });
+        let _ = w.flush();
+        let Some(Ok(line)) = lines.next() else { break };
+        if buffer.is_empty() && line.trim_start().starts_with(':') {
+            if !repl.command(line.trim(), w, e) {
+                break;
+            }
+            continue;
+        }
+        // an empty line ends the entry, even if it seems incomplete
+        let blank = line.trim().is_empty();
+        buffer.push_str(&line);
+        buffer.push('\n');
+        if blank || is_complete(&buffer) {
+            let text = std::mem::take(&mut buffer);
+            let trimmed = text.trim_start();
+            if trimmed.starts_with("query") || trimmed.starts_with("?-") {
+                repl.ask(text, w, e);
+            } else if !trimmed.is_empty() {
+                let name = format!("<repl:{}>", repl.entries_added + 1);
+                repl.push(Entry::Text { name, text }, w, e);
+            }
+        }
+    }
+    ExitStatus::Success
+}
+
+/// Whether the entered text ends an entry, i.e., ends with a statement or part.
+/// Braces within comments and string literals are ignored.
+fn is_complete(text: &str) -> bool {
+    let text = preprocessing::comments_removed(text.to_owned());
+    let (mut depth, mut string_end) = (0i32, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if i < string_end => {}
+            '"' | 'r' => string_end = preprocessing::string_literal_end(&text, i).unwrap_or(0),
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    let trimmed = text.trim_end();
+    depth <= 0 && (trimmed.is_empty() || trimmed.ends_with('.') || trimmed.ends_with('}'))
+}
+
+fn empty_denotation() -> Denotation<Knowledge> {
+    Denotation {
+        truths: Default::default(),
+        unknowns: Default::default(),
+        emissions: Default::default(),
+    }
+}
+
+impl Repl {
+    /// Performs the given command. Returns false if the session ends.
+    fn command(&mut self, line: &str, w: &mut impl Write, e: &mut impl Write) -> bool {
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+        let bare = self.denotation.bare();
+        let _ = match name {
+            ":truths" => writeln!(w, "{:#?}", bare.truths),
+            ":unknowns" => writeln!(w, "{:#?}", bare.unknowns),
+            ":emissions" => writeln!(w, "{:#?}", bare.emissions),
+            ":why" if !arg.is_empty() => {
+                let mut config = self.config.clone();
+                config.command = Command::Explain;
+                config.query = Some(arg.to_owned());
+                match self.run(config, w, &mut std::io::sink()) {
+                    Ok(_) => Ok(()),
+                    Err(failure) => writeln!(e, "{}", failure.message),
+                }
+            }
+            ":undo" => match self.entries.pop() {
+                Some(entry) => {
+                    if !self.rerun(w, e) {
+                        self.entries.push(entry);
+                    }
+                    Ok(())
+                }
+                None => writeln!(e, "nothing to undo"),
+            },
+            ":load" if !arg.is_empty() => {
+                self.push(Entry::File { path: arg.into() }, w, e);
+                Ok(())
+            }
+            ":help" => {
+                for (name, desc) in REPL_COMMAND_DESC_SLICE {
+                    let _ = writeln!(w, "  {: <12}  {}", name, desc);
+                }
+                Ok(())
+            }
+            ":quit" | ":q" => return false,
+            _ => writeln!(e, "unrecognized command `{}`; see `:help`", line),
+        };
+        true
+    }
+
+    /// Adds the entry, unless the resulting program is rejected.
+    fn push(&mut self, entry: Entry, w: &mut impl Write, e: &mut impl Write) {
+        self.entries.push(entry);
+        self.entries_added += 1;
+        if !self.rerun(w, e) {
+            self.entries.pop();
+        }
+    }
+
+    /// Configures runs that output only the answers to queries.
+    fn run_config(&self) -> Config {
+        let mut config = self.config.clone();
+        config.command = Command::Run;
+        config.format = Format::Text;
+        config.query = None;
+        config.present_flag_names.insert("no-deno".into());
+        config
+    }
+
+    /// Prints the answers to the queries in `text`, given the current entries.
+    fn ask(&mut self, text: String, w: &mut impl Write, e: &mut impl Write) {
+        let config = self.run_config();
+        self.entries.push(Entry::Text { name: "<query>".into(), text });
+        if let Err(failure) = self.run(config, w, &mut std::io::sink()) {
+            let _ = writeln!(e, "{}", failure.message);
+        }
+        self.entries.pop();
+    }
+
+    /// Checks and runs the program of the current entries. If it is accepted, updates the
+    /// denotation, and prints its changes and any new warnings. Otherwise, prints the error.
+    /// Returns whether the program was accepted.
+    fn rerun(&mut self, w: &mut impl Write, e: &mut impl Write) -> bool {
+        let config = self.run_config();
+        let mut warnings = Vec::<u8>::new();
+        let outcome = self.run(config, &mut std::io::sink(), &mut warnings);
+        let warnings = String::from_utf8_lossy(&warnings);
+        let outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(failure) => {
+                let _ = writeln!(e, "{}{}", warnings, failure.message);
+                return false;
+            }
+        };
+        // each rendered warning ends with an empty line
+        let warnings: HashSet<String> =
+            warnings.split_inclusive("\n\n").map(str::to_owned).collect();
+        for warning in sorted_vec(warnings.difference(&self.warnings)) {
+            let _ = write!(e, "{}", warning);
+        }
+        self.warnings = warnings;
+        let denotation = outcome.denotation.expect("command is `run`");
+        let changes = changes(&self.denotation, &denotation);
+        if changes.is_empty() {
+            let _ = writeln!(w, "no change to the denotation");
+        }
+        for change in changes {
+            let _ = writeln!(w, "{}", change);
+        }
+        self.denotation = denotation;
+        true
+    }
+
+    fn run(
+        &self,
+        config: Config,
+        w: &mut impl Write,
+        e: &mut impl Write,
+    ) -> Result<Outcome, Failure> {
+        let mut sources = Sources::default();
+        for entry in self.entries.iter() {
+            match entry {
+                Entry::Text { name, text } => {
+                    sources.add(name.clone(), None, text.clone());
+                }
+                Entry::File { path } => {
+                    if let Err(err) = sources.read_file(path) {
+                        let message = format!("error: cannot read `{}`: {}", path.display(), err);
+                        return Err(Failure::new(ExitStatus::LoadError, message));
+                    }
+                }
+            }
+        }
+        run_check(config, &mut sources, w, e)
+    }
+}
+
+/// Describes each atom that became, or stopped being, true, unknown, or emitted, e.g., `+ truth x`.
+fn changes(old: &Denotation<Knowledge>, new: &Denotation<Knowledge>) -> Vec<String> {
+    let mut changes = vec![];
+    let pairs = [
+        ("truth", &old.truths, &new.truths),
+        ("unknown", &old.unknowns, &new.unknowns),
+        ("emission", &old.emissions, &new.emissions),
+    ];
+    for (kind, old, new) in pairs {
+        let atoms = |k: &'_ Knowledge| -> HashSet<Atom> { k.atoms().cloned().collect() };
+        let [old, new] = [atoms(old), atoms(new)];
+        for atom in sorted_vec(new.difference(&old)) {
+            changes.push(format!("+ {} {:?}", kind, atom));
+        }
+        for atom in sorted_vec(old.difference(&new)) {
+            changes.push(format!("- {} {:?}", kind, atom));
+        }
+    }
+    changes
+}
@@ -1,9 +1,14 @@
-use super::config::Config;
+use super::config::{Command, Config, DotGraph, Format, Lint, LintLevel};
 use crate::lang::{
-    dynamics::{Denotation, Knowledge},
+    diagnostic::{Diagnostic, Label, Severity},
+    dynamics::{Answer, Atom, ConcreteInference, Denotation, Knowledge, TruthValue},
+    explain::Proof,
+    json::Json,
+    load::Sources,
+    util::{sorted_vec, CommaSep},
     *,
 };
-use std::collections::HashSet;
+use std::{collections::HashSet, ffi::OsStr, path::Path};
 
 pub fn stdin_to_string() -> Result<String, std::io::Error> {
     use std::io::Read as _;
@@ -12,29 +17,136 @@ pub fn stdin_to_string() -> Result<String, std::io::Error> {
     Ok(buffer)
 }
 
+/// The exit status of the executor process, which distinguishes the ways a run can fail.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ExitStatus {
+    Success = 0,
+    /// A source file could not be read, parsed, or imported.
+    LoadError = 1,
+    /// The command line arguments were not understood.
+    UsageError = 2,
+    /// The program was rejected by static checks, or had denied warnings.
+    StaticError = 3,
+    /// The program was rejected because its termination is uncertain.
+    UnboundedDomainCycle = 4,
+    /// Inference was abandoned after exceeding `--max-atoms`.
+    MaxAtomsExceeded = 5,
+    /// There were warnings, and `--fail-on-warnings` was given.
+    Warnings = 6,
+    /// Some atoms were emitted, and `--fail-on-emissions` was given.
+    Emissions = 7,
+    /// Some files run by the `test` command failed.
+    TestFailures = 8,
+}
+
+/// Why a run failed: the exit status, and the error rendered for output.
+#[derive(Debug)]
+pub struct Failure {
+    pub status: ExitStatus,
+    pub message: String,
+}
+
+/// The result of a successful run.
+#[derive(Debug)]
+pub struct Outcome {
+    /// Absent if the command is `check`.
+    pub denotation: Option<Denotation<Knowledge>>,
+    /// The number of reported warnings, e.g., seal breaks, excluding allowed ones.
+    pub warnings: usize,
+}
+
+/////////////////////////
+
+impl ExitStatus {
+    pub const ALL: [Self; 9] = [
+        Self::Success,
+        Self::LoadError,
+        Self::UsageError,
+        Self::StaticError,
+        Self::UnboundedDomainCycle,
+        Self::MaxAtomsExceeded,
+        Self::Warnings,
+        Self::Emissions,
+        Self::TestFailures,
+    ];
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+    /// The exit status of the `test` command, given the number of files that failed.
+    pub fn of_tests(failures: usize) -> Self {
+        if failures > 0 {
+            Self::TestFailures
+        } else {
+            Self::Success
+        }
+    }
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::LoadError => "a source file could not be read, parsed, or imported",
+            Self::UsageError => "the command line arguments were not understood",
+            Self::StaticError => {
+                "the program was rejected by static checks, or had denied warnings"
+            }
+            Self::UnboundedDomainCycle => "the program has an unbounded domain cycle",
+            Self::MaxAtomsExceeded => "inference was abandoned after exceeding `--max-atoms`",
+            Self::Warnings => "there were warnings, and `--fail-on-warnings` was given",
+            Self::Emissions => "atoms were emitted, and `--fail-on-emissions` was given",
+            Self::TestFailures => "some files run by `test` failed",
+        }
+    }
+}
+
+impl Failure {
+    pub fn new(status: ExitStatus, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+
+impl Outcome {
+    /// The exit status of a successful run, given the opt-in failure flags of `config`.
+    pub fn status(&self, config: &Config) -> ExitStatus {
+        let emitted = self.denotation.as_ref().is_some_and(|d| !d.emissions.is_empty());
+        if config.test("fail-on-warnings") && self.warnings > 0 {
+            ExitStatus::Warnings
+        } else if config.test("fail-on-emissions") && emitted {
+            ExitStatus::Emissions
+        } else {
+            ExitStatus::Success
+        }
+    }
+}
+
+/// Checks and runs the program composed of the given `sources` and their imports.
+/// Requested output is written to `w`, and warnings (in text format) and denied warnings to `e`.
+/// Returns the denotation, unless the command is `check`.
 pub fn run_check(
     config: Config,
-    source: String,
+    sources: &mut Sources,
     w: &mut impl std::io::Write,
-) -> Result<Denotation<Knowledge>, String> {
-    let source = preprocessing::comments_removed(source);
+    e: &mut impl std::io::Write,
+) -> Result<Outcome, Failure> {
+    use ExitStatus as Es;
+    let loaded = sources.load();
+    let query_file = config.query.clone().map(|query| sources.add("<query>".into(), None, query));
+    let sources = &*sources;
+    let render = |diagnostic: Diagnostic| diagnostic.render(sources);
+    let fail =
+        |status: ExitStatus| move |diagnostic: Diagnostic| Failure::new(status, render(diagnostic));
     if config.test("source") {
-        let _ = writeln!(w, "source after preprocessing: <<\n{}\n>>", &source);
-    }
-    let mut parse_result = parse::all_consuming(parse::program)(&source);
-    let program = match &mut parse_result {
-        Err(nom::Err::Error(e)) => {
-            let x = nom::error::convert_error(source.as_str(), e.clone());
-            return Err(x);
+        for (_, source_file) in sources.iter() {
+            let source = preprocessing::comments_removed(source_file.text.clone());
+            let _ = writeln!(
+                w,
+                "source of {} after preprocessing: <<\n{}\n>>",
+                source_file.name, &source
+            );
         }
-        Err(e) => return Err(format!("parse error: {:#?}", e)),
-        Ok((rest, program)) => {
-            assert!(rest.is_empty());
-            program
-        }
-    };
-    if let Some(part_name) = program.repeatedly_defined_part() {
-        return Err(format!("~ ~ ERROR: repeatedly defined part name: {:?}", part_name));
+    }
+    let mut program = loaded.map_err(fail(Es::LoadError))?;
+    let program = &mut program;
+    if let Some(diagnostic) = program.repeatedly_defined_part_diagnostic() {
+        return Err(fail(Es::StaticError)(diagnostic));
     }
     if config.test("ast1") {
         let _ = writeln!(w, "ast before preprocessing: {:#?}", program);
@@ -57,8 +169,12 @@ pub fn run_check(
     eq_classes.normalize_equal_domain_ids(program);
     // println!("AFTER EQ {:#?}", program);
     if let Err(e) = eq_classes.check_primitives() {
-        return Err(format!("domain equivalence class error: {:?}", e));
+        return Err(fail(Es::StaticError)(e.diagnostic()));
     }
+    let query = match query_file {
+        Some(file) => Some(query_atom(sources, file, &eq_classes).map_err(fail(Es::UsageError))?),
+        None => None,
+    };
 
     preprocessing::deanonymize_variables(program);
     if config.test("save") {
@@ -67,49 +183,345 @@ pub fn run_check(
     if config.test("ast2") {
         let _ = writeln!(w, "ast after preprocessing: {:#?}", program);
     }
-    let dumn = program.depended_undefined_names().collect::<HashSet<_>>();
-    if !dumn.is_empty() {
-        let _ = writeln!(w, "~ ~ WARNING: dependend undefined parts: {:?} ~ ~", dumn);
+    let json = config.format == Format::Json;
+    // warnings are output within the JSON, unless a graph is output instead
+    let json_warnings = json && config.dot.is_none();
+    let [mut warnings, mut denied] = [0, 0];
+    let mut warn = |lint: Lint, mut diagnostic: Diagnostic| match config.lint_level(lint) {
+        LintLevel::Allow => {}
+        LintLevel::Warn => {
+            warnings += 1;
+            if !json_warnings {
+                let _ = writeln!(e, "{}", render(diagnostic));
+            }
+        }
+        LintLevel::Deny => {
+            denied += 1;
+            diagnostic.severity = Severity::Error;
+            diagnostic.notes.push(format!("lint `{}` is denied", lint.name()));
+            let _ = writeln!(e, "{}", render(diagnostic));
+        }
+    };
+    for diagnostic in program.depended_undefined_diagnostics() {
+        warn(Lint::DependedUndefined, diagnostic);
     }
     let ep = program.executable(config.executable_config());
     if config.test("ir") {
         let _ = writeln!(w, "internal representation: {:#?}", ep);
     }
     let ep = match ep {
-        Err(e) => return Err(format!("~ ~ ERROR: error constructing executable: {:#?}", e)),
+        Err(err) => return Err(fail(Es::StaticError)(err.diagnostic())),
         Ok(ep) => ep,
     };
     // println!("EP {:#?}", ep);
     if let Some(cycle) = ep.unbounded_domain_cycle() {
-        return Err(format!(
-            "~ ~ ERROR: termination uncertain due to unbounded domain cycle: {:?} ~ ~",
-            cycle
-        ));
+        return Err(fail(Es::UnboundedDomainCycle)(cycle.diagnostic()));
     }
-    if !ep.used_undeclared.is_empty() {
-        let _ = writeln!(
-            w,
-            "~ ~ WARNING: domains undeclared but are variables or have arguements: {:?} ~ ~",
-            ep.used_undeclared
-        );
+    if let Some(unstratified) = ep.unstratified_aggregate() {
+        return Err(fail(Es::StaticError)(unstratified.diagnostic()));
+    }
+    // when denied, these fail like unbounded domain cycles
+    let unbounded_values = ep.unbounded_values();
+    let deny_unbounded_values =
+        !unbounded_values.is_empty() && config.lint_level(Lint::UnboundedValues) == LintLevel::Deny;
+    for unbounded in unbounded_values.iter() {
+        warn(Lint::UnboundedValues, unbounded.diagnostic());
+    }
+    if deny_unbounded_values {
+        let message = "error: aborting due to denied unbounded values";
+        return Err(Failure::new(Es::UnboundedDomainCycle, message));
+    }
+    for diagnostic in ep.used_undeclared_diagnostics() {
+        warn(Lint::UsedUndeclared, diagnostic);
     }
     let pug = program.part_usage_graph();
     // println!("{:#?}", pug);
-    let seal_breaks = pug.iter_breaks(&ep).collect::<HashSet<_>>();
-    if !seal_breaks.is_empty() {
-        let _ = writeln!(w, "~ ~ WARNING: seal breaks: {:#?} ~ ~", seal_breaks);
+    let mut seal_breaks =
+        pug.iter_breaks(&ep).collect::<HashSet<_>>().into_iter().collect::<Vec<_>>();
+    seal_breaks.sort_by_key(|seal_break| {
+        let [m, s] = [seal_break.modifier_span, seal_break.sealer_span];
+        (m.file, m.start, s.file, s.start)
+    });
+    for seal_break in seal_breaks.iter() {
+        warn(Lint::SealBreaks, seal_break.diagnostic());
+    }
+    if denied > 0 {
+        let message = format!("error: aborting due to {} denied warning(s)", denied);
+        return Err(Failure::new(Es::StaticError, message));
     }
-    let denotation_res = ep.denotation();
+    let warnings_json = || {
+        let seal_breaks = seal_breaks.iter().map(|seal_break| seal_break.to_json(sources));
+        let unbounded_values = unbounded_values.iter().map(|unbounded| unbounded.to_json(sources));
+        let unless_allowed = |lint: Lint, json: Json| match config.lint_level(lint) {
+            LintLevel::Allow => Json::Array(vec![]),
+            _ => json,
+        };
+        Json::object([
+            ("seal_breaks", unless_allowed(Lint::SealBreaks, Json::Array(seal_breaks.collect()))),
+            (
+                "used_undeclared",
+                unless_allowed(Lint::UsedUndeclared, ep.used_undeclared_json(sources)),
+            ),
+            (
+                "depended_undefined",
+                unless_allowed(Lint::DependedUndefined, program.depended_undefined_json(sources)),
+            ),
+            (
+                "unbounded_values",
+                unless_allowed(Lint::UnboundedValues, Json::Array(unbounded_values.collect())),
+            ),
+        ])
+    };
+    if config.dot == Some(DotGraph::Parts) {
+        let _ = write!(w, "{}", program.parts_dot(&ep, &seal_breaks));
+        return Ok(Outcome { denotation: None, warnings });
+    }
+    if config.dot == Some(DotGraph::Domains) {
+        let _ = write!(w, "{}", ep.domain_dependency_graph().to_dot());
+        return Ok(Outcome { denotation: None, warnings });
+    }
+    if config.test("strata") {
+        let _ = match ep.domain_dependency_graph().strata() {
+            Ok(strata) => {
+                let strata = sorted_vec(strata.into_iter().map(|(did, stratum)| (stratum, did)));
+                let _ = writeln!(w, "stratified:");
+                for (stratum, dids) in strata.chunk_by(|(a, _), (b, _)| a == b).enumerate() {
+                    let dids = dids.iter().map(|(_, did)| did);
+                    let _ =
+                        writeln!(w, "  {}: {:?}", stratum, CommaSep { iter: dids, spaced: true });
+                }
+                Ok(())
+            }
+            Err(cycle) => writeln!(w, "not stratified, due to the cycle: {}", cycle),
+        };
+    }
+    if config.command == Command::Check {
+        if json {
+            let _ = writeln!(w, "{:#}", Json::object([("warnings", warnings_json())]));
+        }
+        return Ok(Outcome { denotation: None, warnings });
+    }
+    let denotation_res =
+        ep.denotation().map_err(|err| fail(Es::MaxAtomsExceeded)(err.diagnostic()))?;
     if config.test("how") {
         let _ = writeln!(w, "how: {:#?}", ep.how(&denotation_res));
     }
+    if config.command == Command::Explain {
+        if query.is_none() && json {
+            let inferences =
+                ep.how(&denotation_res).iter().map(ConcreteInference::to_json).collect();
+            let json = Json::object([
+                ("inferences", Json::Array(inferences)),
+                ("warnings", warnings_json()),
+            ]);
+            let _ = writeln!(w, "{:#}", json);
+        } else if query.is_none() {
+            for inference in ep.how(&denotation_res) {
+                let antecedents = CommaSep { iter: &inference.antecedents, spaced: true };
+                if inference.antecedents.is_empty() {
+                    let _ = writeln!(w, "{:?}.", inference.consequent);
+                } else {
+                    let _ = writeln!(w, "{:?} :- {:?}.", inference.consequent, antecedents);
+                }
+            }
+        }
+        if let Some(atom) = &query {
+            let denotation = &denotation_res.denotation;
+            let truth_value = denotation.truth_value(atom);
+            let proof = ep.proof(&denotation_res, atom);
+            if config.dot == Some(DotGraph::Proof) {
+                match &proof {
+                    Some(proof) => {
+                        let _ = write!(w, "{}", proof.to_dot());
+                    }
+                    None => {
+                        let _ = writeln!(
+                            e,
+                            "note: {:?} is {}, so it has no proof",
+                            atom,
+                            truth_value.name()
+                        );
+                    }
+                }
+                return Ok(Outcome { denotation: Some(denotation_res.denotation), warnings });
+            }
+            if json {
+                let json = Json::object([
+                    ("atom", atom.to_json()),
+                    ("truth_value", Json::str(truth_value.name())),
+                    ("proof", proof.as_ref().map(Proof::to_json).unwrap_or(Json::Null)),
+                    ("warnings", warnings_json()),
+                ]);
+                let _ = writeln!(w, "{:#}", json);
+                return Ok(Outcome { denotation: Some(denotation_res.denotation), warnings });
+            }
+            match truth_value {
+                TruthValue::True => {
+                    let _ = write!(w, "{}", proof.expect("true"));
+                }
+                TruthValue::Unknown => {
+                    let _ = match ep.why_unknown(denotation, atom) {
+                        Some(why_unknown) => write!(w, "{}", why_unknown),
+                        None => writeln!(
+                            w,
+                            "{:?} is unknown, but no cycle through negation was found",
+                            atom
+                        ),
+                    };
+                }
+                TruthValue::False => {
+                    let _ = write!(w, "{}", ep.why_not(denotation, atom, config.depth));
+                }
+            }
+        }
+        return Ok(Outcome { denotation: Some(denotation_res.denotation), warnings });
+    }
     let denotation = denotation_res.denotation;
-    if !config.test("no-deno") {
-        let _ = if config.test("cluster") {
-            writeln!(w, "denotation: {:#?}", denotation)
-        } else {
-            writeln!(w, "denotation: {:#?}", denotation.bare())
-        };
+    if json {
+        let mut json = denotation.to_json();
+        json.insert("warnings", warnings_json());
+        let ddg = ep.domain_dependency_graph();
+        json.insert("domain_dependencies", ddg.to_json());
+        json.insert("stratification", ddg.stratification_json());
+        if let Some(atom) = &query {
+            let truth_value = Json::str(denotation.truth_value(atom).name());
+            json.insert(
+                "query",
+                Json::object([("atom", atom.to_json()), ("truth_value", truth_value)]),
+            );
+        }
+        let queries = ep.queries().iter().map(|query| {
+            let answers = denotation.answers(query).iter().map(Answer::to_json).collect();
+            Json::object([
+                ("query", Json::str(query_text(query))),
+                ("answers", Json::Array(answers)),
+            ])
+        });
+        json.insert("queries", Json::Array(queries.collect()));
+        let _ = writeln!(w, "{:#}", json);
+    } else {
+        if !config.test("no-deno") {
+            let _ = if config.test("cluster") {
+                writeln!(w, "denotation: {:#?}", denotation)
+            } else {
+                writeln!(w, "denotation: {:#?}", denotation.bare())
+            };
+        }
+        if let Some(atom) = &query {
+            let _ = writeln!(w, "query {:?}: {}", atom, denotation.truth_value(atom).name());
+        }
+        for query in ep.queries() {
+            let _ = writeln!(w, "{}:", query_text(query));
+            let answers = denotation.answers(query);
+            if answers.is_empty() {
+                let _ = writeln!(w, "    no answers");
+            }
+            for Answer { bindings, truth_value } in answers {
+                let bindings = bindings.iter().map(|(vid, atom)| format!("{:?} = {:?}", vid, atom));
+                let bindings = bindings.collect::<Vec<_>>().join(", ");
+                let bindings = if bindings.is_empty() { "yes" } else { &bindings };
+                let _ = writeln!(w, "    {} ({})", bindings, truth_value.name());
+            }
+        }
+    }
+    Ok(Outcome { denotation: Some(denotation), warnings })
+}
+
+/// E.g., `query a(X, _), !b(X)`.
+fn query_text(query: &AnnotatedRule) -> String {
+    let mut rule = query.rule.clone();
+    for ra in rule.root_atoms_mut() {
+        ra.visit_subatoms_mut(&mut |ra| match ra {
+            RuleAtom::Variable { vid, .. } if vid.is_anonymous() => *vid = VariableId("_".into()),
+            _ => {}
+        });
+    }
+    format!("{:?}", StatementKind::Query(rule))
+}
+
+/// Parses, and normalizes the domain ids of, the atom given in the `file` of the query.
+fn query_atom(sources: &Sources, file: FileId, eq_classes: &EqClasses) -> Result<Atom, Diagnostic> {
+    let text = &sources.get(file).expect("query was added").text;
+    let mut ra = match parse::all_consuming(parse::queried_atom)(text) {
+        Ok((_rest, ra)) => ra,
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+            return Err(Diagnostic::from_parse_error(file, text, &e))
+        }
+        Err(nom::Err::Incomplete(_)) => unreachable!("parsers are complete"),
+    };
+    ra.visit_spans_mut(&mut |span| span.file = file);
+    eq_classes.normalize_query(&mut ra);
+    ra.ground().ok_or_else(|| {
+        let label = Label::new(ra.span(), "has variables");
+        Diagnostic::error("E0015", "the queried atom must not have variables", label)
+    })
+}
+
+/// Prints each source file in canonical form. With multiple files, each is preceded by a comment
+/// naming it. Imports are not followed.
+pub fn run_fmt(sources: &Sources, w: &mut impl std::io::Write) -> Result<(), Failure> {
+    let many = sources.iter().count() > 1;
+    for (file, source_file) in sources.iter() {
+        let program = sources.parse(file).map_err(|diagnostic| {
+            Failure::new(ExitStatus::LoadError, diagnostic.render(sources))
+        })?;
+        if many {
+            let _ = writeln!(w, "# {}", source_file.name);
+        }
+        let _ = write!(w, "{}", pretty::formatted(&source_file.text, &program));
+    }
+    Ok(())
+}
+
+/// Runs each file at the input paths, and each `.seaso` file in directories there (recursively),
+/// printing whether each passes, i.e., is checked and run without error. With no input paths, the
+/// current directory is searched. Returns the number of failures, counting each input path that
+/// cannot be read. Fails if no file was run.
+pub fn run_tests(config: &Config, w: &mut impl std::io::Write) -> Result<usize, Failure> {
+    fn run_test(config: &Config, path: &Path, w: &mut impl std::io::Write, failures: &mut usize) {
+        let mut sources = Sources::default();
+        let [mut out, mut err] = [std::io::sink(), std::io::sink()];
+        let pass = sources.read_file(path).is_ok()
+            && run_check(config.clone(), &mut sources, &mut out, &mut err).is_ok();
+        let _ = writeln!(w, "{} {}", if pass { "pass" } else { "FAIL" }, path.display());
+        if !pass {
+            *failures += 1;
+        }
+    }
+    fn rec(config: &Config, dir: &Path, w: &mut impl std::io::Write, counts: &mut [usize; 2]) {
+        let Ok(children) = std::fs::read_dir(dir) else { return };
+        let mut children: Vec<_> = children.filter_map(Result::ok).map(|c| c.path()).collect();
+        children.sort();
+        for child in children {
+            if child.is_dir() {
+                rec(config, &child, w, counts)
+            } else if child.extension() == Some(OsStr::new("seaso")) {
+                counts[0] += 1;
+                run_test(config, &child, w, &mut counts[1])
+            }
+        }
+    }
+    let default_paths = [".".to_owned()];
+    let paths =
+        if config.input_paths.is_empty() { &default_paths } else { &config.input_paths[..] };
+    // the number of files run, and of failures
+    let mut counts = [0, 0];
+    for path in paths.iter().map(Path::new) {
+        match std::fs::metadata(path) {
+            Err(e) => {
+                let _ = writeln!(w, "FAIL {}: cannot read: {}", path.display(), e);
+                counts[1] += 1;
+            }
+            Ok(metadata) if metadata.is_dir() => rec(config, path, w, &mut counts),
+            Ok(_) => {
+                counts[0] += 1;
+                run_test(config, path, w, &mut counts[1])
+            }
+        }
+    }
+    if counts == [0, 0] {
+        let message = "error: no `.seaso` files to test";
+        return Err(Failure::new(ExitStatus::LoadError, message));
     }
-    Ok(denotation)
+    Ok(counts[1])
 }
@@ -0,0 +1,424 @@
+use crate::{
+    dynamics::MaxAtomsExceeded,
+    load::Sources,
+    preprocessing::EquatePrimitivesError,
+    statics::{
+        ArgumentEdge, ExecutableError, ExecutableRuleError, SealBreak, UnboundedDomainCycle,
+        UnboundedValues, UnstratifiedAggregate,
+    },
+    *,
+};
+use std::{collections::HashSet, fmt::Write as _};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A message about a source location, to be rendered for the user alongside a source snippet.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable identifier of the kind of problem, e.g., `E0005`.
+    pub code: &'static str,
+    pub message: String,
+    /// The first label is primary, locating the problem, and is underlined with `^`.
+    /// The rest locate related code, and are underlined with `-`. Never empty.
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+/// A span, with a (possibly empty) message shown where it is underlined.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/////////////////////////
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+impl Label {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Self { span, message: message.into() }
+    }
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, primary: Label) -> Self {
+        Self::new(Severity::Error, code, message, primary)
+    }
+    pub fn warning(code: &'static str, message: impl Into<String>, primary: Label) -> Self {
+        Self::new(Severity::Warning, code, message, primary)
+    }
+    fn new(
+        severity: Severity,
+        code: &'static str,
+        message: impl Into<String>,
+        primary: Label,
+    ) -> Self {
+        Self { severity, code, message: message.into(), labels: vec![primary], notes: vec![] }
+    }
+    pub fn primary(&self) -> &Label {
+        &self.labels[0]
+    }
+    pub fn with_label(mut self, label: Label) -> Self {
+        self.labels.push(label);
+        self
+    }
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders this diagnostic in the style of rustc, e.g.,
+    /// ```text
+    /// error[E0005]: variable `X` has two types: `int` and `str`
+    ///  --> example.seaso:5:16
+    ///   |
+    /// 5 | rule b(X) :- a(X).
+    ///   |                ^ `int` here
+    ///   |        - `str` here
+    /// ```
+    /// Labels with the default span (i.e., not located in the source) are omitted.
+    pub fn render(&self, sources: &Sources) -> String {
+        let mut s = String::new();
+        let _ = writeln!(s, "{}[{}]: {}", self.severity.name(), self.code, self.message);
+        let located: Vec<(&Label, char)> = self
+            .labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| (label, if i == 0 { '^' } else { '-' }))
+            .filter(|(label, _)| label.span.line > 0)
+            .collect();
+        let gutter = located.iter().map(|(label, _)| label.span.line.to_string().len()).max();
+        let gutter = gutter.unwrap_or(0);
+        // labels are grouped by file (primary file first), then shown in order of lines
+        let mut files: Vec<FileId> = located.iter().map(|(label, _)| label.span.file).collect();
+        let mut seen = HashSet::<FileId>::default();
+        files.retain(|file| seen.insert(*file));
+        for file in files {
+            let Some(source_file) = sources.get(file) else { continue };
+            let source = source_file.text.as_str();
+            let in_file = || located.iter().filter(move |(label, _)| label.span.file == file);
+            let first = in_file().next().expect("file has a label");
+            let _ = writeln!(s, "{:gutter$}--> {}:{:?}", "", source_file.name, first.0.span);
+            let _ = writeln!(s, "{:gutter$} |", "");
+            let mut lines: Vec<u32> = in_file().map(|(label, _)| label.span.line).collect();
+            lines.sort();
+            lines.dedup();
+            for line in lines {
+                Self::render_line(&mut s, source, line, gutter, in_file());
+            }
+        }
+        if !located.is_empty() && !self.notes.is_empty() {
+            let _ = writeln!(s, "{:gutter$} |", "");
+        }
+        for note in &self.notes {
+            let _ = writeln!(s, "{:gutter$} = note: {}", "", note);
+        }
+        s
+    }
+
+    /// Renders the given line of `source`, underlining those of `labels` which start on it.
+    fn render_line<'a>(
+        s: &mut String,
+        source: &str,
+        line: u32,
+        gutter: usize,
+        labels: impl Iterator<Item = &'a (&'a Label, char)>,
+    ) {
+        let text = source.lines().nth(line as usize - 1).unwrap_or("");
+        let _ = writeln!(s, "{:>gutter$} | {}", line, text);
+        for (label, marker) in labels.filter(|(label, _)| label.span.line == line) {
+            let indent: String = text
+                .chars()
+                .take(label.span.col as usize - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let width = source
+                .get(label.span.start as usize..label.span.end as usize)
+                .and_then(|spanned| spanned.lines().next())
+                .map(|spanned| spanned.chars().count())
+                .unwrap_or(0)
+                .max(1);
+            let underline: String = std::iter::repeat_n(*marker, width).collect();
+            let _ = write!(s, "{:gutter$} | {}{}", "", indent, underline);
+            if !label.message.is_empty() {
+                let _ = write!(s, " {}", label.message);
+            }
+            s.push('\n');
+        }
+    }
+
+    /// Describes the failure to parse `source`. The primary span is the innermost location
+    /// at which parsing failed. The enclosing locations are given as notes.
+    pub fn from_parse_error(
+        file: FileId,
+        source: &str,
+        e: &nom::error::VerboseError<&str>,
+    ) -> Self {
+        use nom::error::VerboseErrorKind;
+        let line_starts = util::line_starts(source);
+        let locate = |rest: &str| {
+            let start = (source.len() - rest.len()) as u32;
+            let mut span = Span { file, start, end: start, ..Span::default() };
+            span.locate(source, &line_starts);
+            span
+        };
+        let describe = |kind: &VerboseErrorKind| match kind {
+            VerboseErrorKind::Char(c) => format!("expected `{}`", c),
+            VerboseErrorKind::Context(context) => format!("while parsing {}", context),
+            VerboseErrorKind::Nom(nom::error::ErrorKind::Eof) => "unexpected input".to_owned(),
+            VerboseErrorKind::Nom(kind) => format!("expected {}", kind.description()),
+        };
+        let mut errors = e.errors.iter();
+        let Some((rest, kind)) = errors.next() else {
+            let span = Span { file, ..Span::default() };
+            return Self::error("E0001", "syntax error", Label::new(span, ""));
+        };
+        let mut diagnostic =
+            Self::error("E0001", "syntax error", Label::new(locate(rest), describe(kind)));
+        for (rest, kind) in errors {
+            diagnostic = diagnostic.with_note(format!("{} at {:?}", describe(kind), locate(rest)));
+        }
+        diagnostic
+    }
+}
+
+impl Program {
+    /// Warns about each use of an undefined part.
+    pub fn depended_undefined_diagnostics(&self) -> impl Iterator<Item = Diagnostic> + '_ {
+        self.depended_undefined().map(|(part, part_name)| {
+            Diagnostic::warning(
+                "W0001",
+                format!("{:?} uses undefined {:?}", part.name, part_name),
+                Label::new(part.span, format!("{:?} is never defined", part_name)),
+            )
+        })
+    }
+    /// Errs if any part is defined more than once.
+    pub fn repeatedly_defined_part_diagnostic(&self) -> Option<Diagnostic> {
+        self.repeatedly_defined_part().map(|part| {
+            Diagnostic::error(
+                "E0002",
+                format!("{:?} is defined more than once", part.name),
+                Label::new(part.span, "redefined here"),
+            )
+        })
+    }
+}
+
+impl ExecutableProgram {
+    /// Warns about each domain that is used, but undeclared.
+    pub fn used_undeclared_diagnostics(&self) -> impl Iterator<Item = Diagnostic> + '_ {
+        let mut used_undeclared: Vec<_> = self.used_undeclared.iter().collect();
+        used_undeclared.sort_by_key(|(did, span)| (span.file, span.start, *did));
+        used_undeclared.into_iter().map(|(did, &span)| {
+            Diagnostic::warning(
+                "W0002",
+                format!("domain `{:?}` is used but not declared", did),
+                Label::new(span, "used here"),
+            )
+            .with_note("domains used as variables or with arguments should be declared")
+        })
+    }
+}
+
+impl SealBreak<'_> {
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::warning(
+            "W0003",
+            format!("{:?} breaks the seal on `{:?}`", self.modifier, self.did),
+            Label::new(self.modifier_span, format!("modifies `{:?}`", self.did)),
+        )
+        .with_label(Label::new(self.sealer_span, format!("sealed by {:?}", self.sealer)))
+        .with_note(format!("{:?} does not know {:?}", self.sealer, self.modifier))
+    }
+}
+
+impl UnboundedDomainCycle<'_> {
+    /// Labels the construct (in a rule consequent) introducing each edge of the cycle.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let label = |edge: &ArgumentEdge| {
+            Label::new(edge.span, format!("`{:?}` contains `{:?}`", edge.outer, edge.inner))
+        };
+        let [first, rest @ ..] = self.edges.as_slice() else { unreachable!("cycles have edges") };
+        let mut diagnostic = Diagnostic::error(
+            "E0011",
+            format!(
+                "termination uncertain due to unbounded domain cycle through `{:?}`",
+                first.outer
+            ),
+            label(first),
+        );
+        for edge in rest {
+            diagnostic = diagnostic.with_label(label(edge));
+        }
+        let dids = std::iter::once(first.outer).chain(self.edges.iter().map(|edge| edge.inner));
+        let cycle: Vec<String> = dids.map(|did| format!("{:?}", did)).collect();
+        diagnostic
+            .with_note(format!("the cycle is {}", cycle.join(" -> ")))
+            .with_note("rules may infer ever larger atoms along this cycle")
+    }
+}
+
+impl UnboundedValues<'_> {
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::warning(
+            "W0004",
+            format!(
+                "termination uncertain, as rules may compute ever new values of `{:?}`",
+                self.vid
+            ),
+            Label::new(self.condition.span(), format!("computes `{:?}`", self.vid)),
+        )
+        .with_label(Label::new(self.consequent.span(), "inferred here"))
+        .with_label(Label::new(self.antecedent.span(), "from an antecedent depending on it"))
+        .with_note("bound inference with `--max-atoms`, or deny this lint to reject such programs")
+    }
+}
+
+impl UnstratifiedAggregate<'_> {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = match self.did {
+            Some(did) => Diagnostic::error(
+                "E0019",
+                format!("aggregate over `{:?}` depends on its own result", did),
+                Label::new(self.aggregate.span, format!("aggregates over `{:?}`", did)),
+            ),
+            None => Diagnostic::error(
+                "E0019",
+                "aggregates require a stratified program",
+                Label::new(self.aggregate.span, "aggregate here"),
+            ),
+        };
+        diagnostic
+            .with_note(format!("due to the cycle: {}", self.cycle))
+            .with_note("aggregates, like negation (`!`), are evaluated over complete domains")
+    }
+}
+
+impl MaxAtomsExceeded {
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(
+            "E0014",
+            format!("inference abandoned after more than {} atoms were inferred", self.max_atoms),
+            Label::new(Span::default(), ""),
+        )
+        .with_note("the limit is set with `--max-atoms`")
+    }
+}
+
+impl EquatePrimitivesError<'_> {
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(
+            "E0010",
+            format!("primitive domains are equated: {:?}", self.eq_class),
+            Label::new(self.span, "equated here"),
+        )
+    }
+}
+
+impl ExecutableError<'_> {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::ConflictingDefinitions { did, params, spans, .. } => Diagnostic::error(
+                "E0003",
+                format!("domain `{:?}` has conflicting definitions", did),
+                Label::new(spans[1], format!("defined with parameters {:?}", params[1])),
+            )
+            .with_label(Label::new(spans[0], format!("defined with parameters {:?}", params[0]))),
+            Self::DefiningPrimitive { did, span, .. } => Diagnostic::error(
+                "E0004",
+                format!("primitive domain `{:?}` cannot be defined", did),
+                Label::new(*span, "defined here"),
+            ),
+            Self::ExecutableRuleError { rule, err, .. } if rule.consequents.is_empty() => {
+                let query = format!("{:?}", StatementKind::Query((*rule).clone()));
+                err.diagnostic().with_note(format!("in `{}`", query))
+            }
+            Self::ExecutableRuleError { rule, err, .. } => {
+                err.diagnostic().with_note(format!("in rule `{:?}`", rule))
+            }
+        }
+    }
+}
+
+impl ExecutableRuleError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            Self::RepeatedlyDefinedPart { part_name } => Diagnostic::error(
+                "E0002",
+                format!("{:?} is defined more than once", part_name),
+                Label::new(Span::default(), ""),
+            ),
+            Self::OneVariableTwoTypes { vid, domains, spans } => Diagnostic::error(
+                "E0005",
+                format!(
+                    "variable `{:?}` has two types: `{:?}` and `{:?}`",
+                    vid, domains[0], domains[1]
+                ),
+                Label::new(spans[0], format!("`{:?}` here", domains[0])),
+            )
+            .with_label(Label::new(spans[1], format!("`{:?}` here", domains[1]))),
+            Self::MistypedArgument { constructor, expected, got, span } => Diagnostic::error(
+                "E0006",
+                format!("mistyped argument of `{:?}`", constructor),
+                Label::new(*span, format!("expected `{:?}`, found `{:?}`", expected, got)),
+            ),
+            Self::VariableNotEnumerable { vid, span } => Diagnostic::error(
+                "E0007",
+                format!("variable `{:?}` is not enumerable", vid),
+                Label::new(*span, "not bound by any positive antecedent or condition"),
+            ),
+            Self::WrongArity { did, param_count, arg_count, span } => Diagnostic::error(
+                "E0008",
+                format!(
+                    "`{:?}` expects {} argument(s), but was given {}",
+                    did, param_count, arg_count
+                ),
+                Label::new(*span, format!("given {} argument(s)", arg_count)),
+            ),
+            Self::NoTypes { vid, span } => Diagnostic::error(
+                "E0009",
+                format!("variable `{:?}` has no type", vid),
+                Label::new(*span, "type cannot be inferred"),
+            )
+            .with_note("ascribe a type to the variable, e.g., `X:int`"),
+            Self::MistypedOperand { expected, got, span } => Diagnostic::error(
+                "E0016",
+                "mistyped operand of a condition",
+                Label::new(*span, format!("expected `{:?}`, found `{:?}`", expected, got)),
+            ),
+            Self::UncomparableOperands { did, op, span } => Diagnostic::error(
+                "E0017",
+                format!("values of `{:?}` cannot be compared with `{}`", did, op.symbol()),
+                Label::new(*span, format!("compares `{:?}` values", did)),
+            )
+            .with_note(
+                "only integers and strings are ordered, but any values compare with `=` and `!=`",
+            ),
+            Self::WrongBuiltinArity { name, arity, arg_count, span } => {
+                let expected = match arity {
+                    Some(arity) => format!("{} argument(s)", arity),
+                    None => "one or more arguments".to_owned(),
+                };
+                Diagnostic::error(
+                    "E0018",
+                    format!("`{}` expects {}, but was given {}", name, expected, arg_count),
+                    Label::new(*span, format!("given {} argument(s)", arg_count)),
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{
+    explain::{Proof, ProofNode, ProofStep},
+    statics::{DependencyScc, DomainDependencyGraph, SealBreak},
+    *,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+};
+
+/////////////////////////
+
+/// `s` as a quoted DOT string.
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+////////// RENDERERS //////////
+
+impl Proof {
+    /// A graph with an edge from each atom to each of its antecedents.
+    /// Negative antecedents are drawn dashed, and facts as boxes.
+    pub fn to_dot(&self) -> String {
+        let mut s = String::from("digraph proof {\n");
+        for (node, ProofNode { atom, step }) in self.nodes.iter().enumerate() {
+            let shape = match step {
+                ProofStep::Inferred { antecedents } if antecedents.is_empty() => "box",
+                ProofStep::Underivable => "plaintext",
+                _ => "ellipse",
+            };
+            let label = quoted(&format!("{:?}", atom));
+            writeln!(s, "  n{} [label={}, shape={}];", node, label, shape).expect("to string");
+        }
+        for (node, ProofNode { step, .. }) in self.nodes.iter().enumerate() {
+            if let ProofStep::Inferred { antecedents } = step {
+                for (sign, antecedent) in antecedents {
+                    let style = match sign {
+                        Sign::Pos => "",
+                        Sign::Neg => " [style=dashed, label=\"not\"]",
+                    };
+                    writeln!(s, "  n{} -> n{}{};", node, antecedent, style).expect("to string");
+                }
+            }
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+impl StatementAt {
+    /// E.g., `p` for statements in part `p`, or `line 13` for the statement outside any part at
+    /// the given span.
+    fn dot_label(&self, span: Span) -> String {
+        match self {
+            Self::InPart { part_name } => part_name.0.clone(),
+            Self::AnonPart { .. } => format!("line {}", span.line),
+        }
+    }
+}
+
+impl Program {
+    /// A graph with an edge from each part to each part it uses, as declared (i.e., not
+    /// transitively closed). Parts that seal domains are drawn bold, listing the domains.
+    /// Each seal break is a red edge from the modifier to the sealer, labelled with the domain.
+    /// Statements outside any part are only drawn if they are involved in seal breaks, and are
+    /// labelled by their line.
+    pub fn parts_dot(&self, ep: &ExecutableProgram, seal_breaks: &[SealBreak]) -> String {
+        let mut sealed = BTreeMap::<String, BTreeSet<&DomainId>>::default();
+        for (did, dsm) in ep.sealers_modifiers.iter() {
+            for (sealer, &span) in dsm.sealers.iter() {
+                sealed.entry(sealer.dot_label(span)).or_default().insert(did);
+            }
+        }
+        let mut verts = BTreeSet::<String>::default();
+        let mut uses = BTreeSet::<[&str; 2]>::default();
+        for part in self.parts.iter() {
+            verts.insert(part.name.0.clone());
+            for used in part.uses.iter() {
+                verts.insert(used.0.clone());
+                if used != &part.name {
+                    uses.insert([&part.name.0, &used.0]);
+                }
+            }
+        }
+        let mut breaks = BTreeSet::<[String; 3]>::default();
+        for seal_break in seal_breaks {
+            let SealBreak { modifier, sealer, modifier_span, sealer_span, .. } = seal_break;
+            let [modifier, sealer] =
+                [(modifier, modifier_span), (sealer, sealer_span)].map(|(at, &span)| {
+                    let label = at.dot_label(span);
+                    verts.insert(label.clone());
+                    label
+                });
+            breaks.insert([modifier, sealer, seal_break.did.0.clone()]);
+        }
+
+        let mut s = String::from("digraph parts {\n");
+        for vert in verts.iter() {
+            let attributes = match sealed.get(vert) {
+                Some(dids) => {
+                    let dids: Vec<&str> = dids.iter().map(|did| did.0.as_str()).collect();
+                    let label = format!("{}\nseals {}", vert, dids.join(", "));
+                    format!(" [label={}, style=bold]", quoted(&label))
+                }
+                None => String::new(),
+            };
+            writeln!(s, "  {}{};", quoted(vert), attributes).expect("to string");
+        }
+        for [x, y] in uses {
+            writeln!(s, "  {} -> {};", quoted(x), quoted(y)).expect("to string");
+        }
+        for [modifier, sealer, did] in breaks {
+            let [modifier, sealer, did] = [&modifier, &sealer, &did].map(|x| quoted(x));
+            writeln!(s, "  {} -> {} [color=red, fontcolor=red, label={}];", modifier, sealer, did)
+                .expect("to string");
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+impl DomainDependencyGraph<'_> {
+    /// A graph with an edge from each antecedent domain to each consequent domain.
+    /// Negative edges are drawn dashed. Strongly connected components of several domains are
+    /// drawn as clusters, and those with negative edges (where unknowns arise) are red.
+    pub fn to_dot(&self) -> String {
+        let mut s = String::from("digraph domains {\n");
+        for (i, DependencyScc { domains, negative }) in self.sccs.iter().enumerate() {
+            let color = if *negative { "red" } else { "black" };
+            let clustered = domains.len() > 1;
+            if clustered {
+                writeln!(s, "  subgraph cluster_{} {{", i).expect("to string");
+                writeln!(s, "    color={};", color).expect("to string");
+            }
+            for did in domains {
+                let indent = if clustered { "    " } else { "  " };
+                writeln!(s, "{}{} [color={}];", indent, quoted(&did.0), color).expect("to string");
+            }
+            if clustered {
+                s.push_str("  }\n");
+            }
+        }
+        for (digraph, style) in [(&self.positive, ""), (&self.negative, " [style=dashed]")] {
+            for [x, y] in digraph.edges() {
+                writeln!(s, "  {} -> {}{};", quoted(&x.0), quoted(&y.0), style).expect("to string");
+            }
+        }
+        s.push_str("}\n");
+        s
+    }
+}
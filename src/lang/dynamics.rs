@@ -1,6 +1,12 @@
-use crate::*;
-use core::fmt::Debug;
-use std::collections::{HashMap, HashSet};
+use crate::{
+    plan::{JoinPlan, JoinStep, Lookup},
+    *,
+};
+use core::{cmp::Ordering, fmt::Debug};
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 /// Concrete counterpart to `RuleAtom` with no domain info
 #[derive(Ord, PartialOrd, Hash, Eq, PartialEq, Clone)]
@@ -12,11 +18,17 @@ pub enum Atom {
 pub struct Bare<T>(T);
 
 /// A store of atoms, grouped by domain for ease of lookup.
-#[derive(Clone, Default, PartialEq, Eq)]
+/// Atoms can additionally be indexed by their arguments; see `Knowledge::ensure_index`.
+/// Atoms are only added with `Knowledge::insert`, which keeps the indexes up to date.
+#[derive(Default)]
 pub struct Knowledge {
-    pub map: HashMap<DomainId, HashSet<Atom>>,
+    map: HashMap<DomainId, HashSet<Rc<Atom>>>,
+    indexes: HashMap<DomainId, HashMap<usize, ArgumentIndex>>,
 }
 
+/// Maps each argument (at some fixed position) to the atoms with that argument, shared with `map`.
+type ArgumentIndex = HashMap<Atom, Vec<Rc<Atom>>>;
+
 /// Three instances of `Knowledge`, denoting truths, unknowns, and emissions.
 /// Invariants:
 /// 1. truths and unknowns are disjoint.
@@ -34,18 +46,49 @@ pub struct Denotation<T: Debug> {
 
 /// Used internally when concretizing a rule. Conceptually, is a map from VariableId to Atom.
 /// Here, implemented with a vector so that the state of its mappings can be easily saved and reverted.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct VariableAssignments {
     assignments: Vec<(VariableId, Atom)>,
 }
 
+#[derive(Clone)]
 pub struct Literal {
     pub sign: Sign,
     pub atom: Atom,
 }
 
+/// Read-only inputs to one rule's inference stage, shared by each of its recursive steps.
+struct InferenceContext<'a> {
+    v2d: &'a VariableTypes,
+    neg: ComplementKnowledge<'a>,
+    pos_r: &'a Knowledge,
+    /// Atoms newly inferred in the previous round. Subset of `pos_r`.
+    delta: &'a Knowledge,
+    /// Index of the positive antecedent restricted to `delta`, if any.
+    delta_idx: Option<usize>,
+    executable_config: &'a ExecutableConfig,
+}
+
+/// Which atoms of `pos_r` a positive antecedent ranges over.
+#[derive(Debug, Copy, Clone)]
+enum AtomSource {
+    /// Those not in `delta`.
+    Old,
+    /// Those in `delta`.
+    Delta,
+    All,
+}
+
+/// What the aggregates of a rule's conditions range over.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct AggregateScope<'a> {
+    pub v2d: &'a VariableTypes,
+    /// The truths, if they are complete. Otherwise, aggregates are undefined.
+    pub truths: Option<&'a Knowledge>,
+}
+
 /// Encodes a snapshot of a growing `VariableAssignments` structure. Used to revert prior states.
-struct StateToken {
+pub(crate) struct StateToken {
     assignments_count: usize,
 }
 
@@ -55,6 +98,29 @@ pub enum ComplementKnowledge<'a> {
     Empty,
     ComplementOf(&'a Knowledge),
 }
+/// Returned by `ExecutableProgram::denotation` when `ExecutableConfig::max_atoms` is exceeded.
+#[derive(Debug)]
+pub struct MaxAtomsExceeded {
+    pub max_atoms: usize,
+}
+
+/// The truth value of an atom, in a denotation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum TruthValue {
+    True,
+    Unknown,
+    False,
+}
+
+/// An answer to a query: a binding of each of its named variables, under which its literals are
+/// true or unknown (never false), combined as a conjunction.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Answer {
+    /// Ordered by variable.
+    pub bindings: Vec<(VariableId, Atom)>,
+    pub truth_value: TruthValue,
+}
+
 #[derive(Debug)]
 pub struct ConcreteInference {
     pub consequent: Atom,
@@ -72,8 +138,11 @@ impl ExecutableProgram {
         let mut va = VariableAssignments::default();
         let mut concrete_inferences = Vec::<ConcreteInference>::default();
         let neg = ComplementKnowledge::ComplementOf(&dr.prev_truths);
+        let rules: Vec<&AnnotatedRule> = self.annotated_rules.iter().collect();
         let _ = self.big_step_inference(
+            &rules,
             neg,
+            Knowledge::default(),
             &mut pos_w,
             &mut va,
             &mut |va, consequent, antecedents| {
@@ -89,42 +158,92 @@ impl ExecutableProgram {
         );
         concrete_inferences
     }
+    /// Computes the least fixpoint of the given rules, given fixed negative knowledge `neg`,
+    /// extending the truths already known in `pos_r`.
+    /// Evaluation is semi-naive: after the first round, each rule is only applied
+    /// such that at least one positive antecedent is matched by an atom inferred in the previous round.
+    /// Fails if more than `max_atoms` atoms are inferred, as checked after each round.
     fn big_step_inference(
         &self,
+        rules: &[&AnnotatedRule],
         neg: ComplementKnowledge,
+        mut pos_r: Knowledge,
         pos_w: &mut Knowledge,
         va: &mut VariableAssignments,
         visit_inserted: &mut impl FnMut(&VariableAssignments, &RuleAtom, &Vec<RuleLiteral>),
-    ) -> Knowledge {
-        let mut pos_r = Knowledge::default(); // self.starting_facts();
+    ) -> Result<Knowledge, MaxAtomsExceeded> {
+        for rule in rules {
+            for (did, position) in rule.indexed_arguments() {
+                pos_r.ensure_index(did, position);
+            }
+        }
+        let mut delta = Knowledge::default();
+        let mut first_round = true;
         loop {
-            for AnnotatedRule { v2d, rule } in &self.annotated_rules {
-                rule.inference_stage(
-                    v2d,
-                    neg,
-                    &pos_r,
-                    pos_w,
-                    va,
-                    visit_inserted,
-                    &self.executable_config,
-                )
+            for AnnotatedRule { v2d, rule, plans } in rules.iter().copied() {
+                let delta_idxs: Vec<Option<usize>> = if first_round {
+                    // every rule is applied to all that is known
+                    vec![None]
+                } else {
+                    // skip joins that are certainly empty
+                    let delta_nonempty = |antecedent: &RuleLiteral| {
+                        let did = antecedent.ra.domain_id(v2d).expect("static checked");
+                        antecedent.sign == Sign::Pos && delta.len_in_domain(did) > 0
+                    };
+                    let iter = rule.antecedents.iter().enumerate();
+                    iter.filter(|(_, antecedent)| delta_nonempty(antecedent))
+                        .map(|(idx, _)| Some(idx))
+                        .collect()
+                };
+                for delta_idx in delta_idxs {
+                    let plan = plans.get(delta_idx);
+                    let ctx = InferenceContext {
+                        v2d,
+                        neg,
+                        pos_r: &pos_r,
+                        delta: &delta,
+                        delta_idx,
+                        executable_config: &self.executable_config,
+                    };
+                    rule.inference_stage(&ctx, plan, pos_w, va, visit_inserted)
+                }
             }
             if pos_w.is_empty() {
-                return pos_r;
+                return Ok(pos_r);
+            }
+            delta = std::mem::take(pos_w);
+            pos_r.absorb_disjoint(&delta);
+            first_round = false;
+            if let Some(max_atoms) = self.executable_config.max_atoms {
+                if pos_r.len() > max_atoms {
+                    return Err(MaxAtomsExceeded { max_atoms });
+                }
             }
-            pos_r.absorb_disjoint(pos_w);
         }
     }
 
-    pub fn denotation(&self) -> DenotationResult {
+    /// Computes the well-founded denotation, with the alternating fixpoint.
+    /// Stratified programs are instead evaluated stratum by stratum, in a single pass.
+    pub fn denotation(&self) -> Result<DenotationResult, MaxAtomsExceeded> {
+        match self.domain_dependency_graph().strata() {
+            Ok(strata) => self.stratified_denotation(&strata),
+            Err(_) => self.alternating_denotation(),
+        }
+    }
+
+    /// Computes the well-founded denotation with the alternating fixpoint, regardless of strata.
+    pub(crate) fn alternating_denotation(&self) -> Result<DenotationResult, MaxAtomsExceeded> {
+        let rules: Vec<&AnnotatedRule> = self.annotated_rules.iter().collect();
         let mut pos_w = Knowledge::default(); // self.starting_facts();
         let mut va = VariableAssignments::default();
         let mut interpretations = vec![self.big_step_inference(
+            &rules,
             ComplementKnowledge::Empty,
+            Knowledge::default(),
             &mut pos_w,
             &mut va,
             &mut |_, _, _| {},
-        )];
+        )?];
         loop {
             if interpretations.len() % 2 == 1 {
                 if let [.., a, b, c] = interpretations.as_mut_slice() {
@@ -139,31 +258,84 @@ impl ExecutableProgram {
                             }
                             unknowns
                         };
-                        let emissions = Knowledge {
-                            map: truths
-                                .map
-                                .iter()
-                                .filter_map(|(did, set)| {
-                                    if self.emissive.contains(did) {
-                                        Some((did.clone(), set.clone()))
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .collect(),
-                        };
+                        let emissions = self.emissions(&truths);
                         let denotation = Denotation { truths, unknowns, emissions };
-                        return DenotationResult { denotation, prev_truths };
+                        return Ok(DenotationResult { denotation, prev_truths });
                     }
                 }
             }
             let neg = ComplementKnowledge::ComplementOf(interpretations.iter().last().unwrap());
             assert!(pos_w.map.is_empty());
             assert!(va.assignments.is_empty());
-            let pos = self.big_step_inference(neg, &mut pos_w, &mut va, &mut |_, _, _| {});
+            let pos = self.big_step_inference(
+                &rules,
+                neg,
+                Knowledge::default(),
+                &mut pos_w,
+                &mut va,
+                &mut |_, _, _| {},
+            )?;
             interpretations.push(pos);
         }
     }
+
+    /// Computes the denotation of a stratified program, given the stratum of each domain.
+    /// Each rule is applied in the lowest stratum of its consequents, by which point each domain
+    /// it negates is complete. Thus, there are no unknowns.
+    fn stratified_denotation(
+        &self,
+        strata: &HashMap<&DomainId, usize>,
+    ) -> Result<DenotationResult, MaxAtomsExceeded> {
+        let subconsequence = self.executable_config.subconsequence;
+        let mut strata_rules = Vec::<Vec<&AnnotatedRule>>::default();
+        for annotated_rule in self.annotated_rules.iter() {
+            let consequent_dids = annotated_rule.consequent_domains(subconsequence);
+            let Some(stratum) = consequent_dids.iter().map(|did| strata[did]).min() else {
+                continue;
+            };
+            if strata_rules.len() <= stratum {
+                strata_rules.resize_with(stratum + 1, Vec::default);
+            }
+            strata_rules[stratum].push(annotated_rule);
+        }
+        let mut pos_w = Knowledge::default();
+        let mut va = VariableAssignments::default();
+        let mut truths = Knowledge::default();
+        for rules in strata_rules.iter().filter(|rules| !rules.is_empty()) {
+            // negated domains are in lower strata, which are complete
+            let lower = truths.clone();
+            let neg = ComplementKnowledge::ComplementOf(&lower);
+            truths = self.big_step_inference(
+                rules,
+                neg,
+                truths,
+                &mut pos_w,
+                &mut va,
+                &mut |_, _, _| {},
+            )?;
+        }
+        let emissions = self.emissions(&truths);
+        let denotation = Denotation { unknowns: Knowledge::default(), emissions, truths };
+        Ok(DenotationResult { prev_truths: denotation.truths.clone(), denotation })
+    }
+
+    /// The atoms of emissive domains among `truths`.
+    fn emissions(&self, truths: &Knowledge) -> Knowledge {
+        Knowledge {
+            indexes: Default::default(),
+            map: truths
+                .map
+                .iter()
+                .filter_map(|(did, set)| {
+                    if self.emissive.contains(did) {
+                        Some((did.clone(), set.clone()))
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+        }
+    }
 }
 
 impl Denotation<Knowledge> {
@@ -171,24 +343,108 @@ impl Denotation<Knowledge> {
         let Self { truths, unknowns, emissions } = self;
         Denotation { truths: Bare(truths), unknowns: Bare(unknowns), emissions: Bare(emissions) }
     }
+    pub fn truth_value(&self, atom: &Atom) -> TruthValue {
+        let did = atom.domain_id();
+        if self.truths.contains(did, atom) {
+            TruthValue::True
+        } else if self.unknowns.contains(did, atom) {
+            TruthValue::Unknown
+        } else {
+            TruthValue::False
+        }
+    }
+    /// The answers to the given query (see `ExecutableProgram::queries`), ordered by their bindings.
+    /// Anonymous variables are not bound. Where answers bind the same variables to the same atoms
+    /// (differing only in their anonymous variables), just the one of the greatest truth is kept.
+    pub fn answers(&self, query: &AnnotatedRule) -> Vec<Answer> {
+        let AnnotatedRule { v2d, rule, plans } = query;
+        // positive literals are matched against the atoms that are not false
+        let mut not_false = self.truths.clone();
+        for (did, position) in query.indexed_arguments() {
+            not_false.ensure_index(did, position);
+        }
+        not_false.absorb_disjoint(&self.unknowns);
+        let empty = Knowledge::default();
+        let executable_config = ExecutableConfig::default();
+        let ctx = InferenceContext {
+            v2d,
+            neg: ComplementKnowledge::ComplementOf(&self.truths),
+            pos_r: &not_false,
+            delta: &empty,
+            delta_idx: None,
+            executable_config: &executable_config,
+        };
+        let mut answers = HashMap::<Vec<(VariableId, Atom)>, TruthValue>::default();
+        rule.join_rec(&ctx, &mut VariableAssignments::default(), &plans.all.steps, &mut |va| {
+            let truth_value = rule.antecedents.iter().fold(TruthValue::True, |tv, antecedent| {
+                let literal = antecedent.concretize(va).expect("bound");
+                let atom_tv = self.truth_value(&literal.atom);
+                tv.and(if literal.sign == Sign::Pos { atom_tv } else { atom_tv.negated() })
+            });
+            let mut bindings: Vec<_> =
+                va.assignments.iter().filter(|(vid, _)| !vid.is_anonymous()).cloned().collect();
+            bindings.sort();
+            let best = answers.entry(bindings).or_insert(truth_value);
+            if truth_value == TruthValue::True {
+                *best = TruthValue::True;
+            }
+        });
+        let answers =
+            answers.into_iter().map(|(bindings, truth_value)| Answer { bindings, truth_value });
+        let mut answers: Vec<_> = answers.collect();
+        answers.sort_by(|a, b| a.bindings.cmp(&b.bindings));
+        answers
+    }
+}
+
+impl TruthValue {
+    pub fn negated(self) -> Self {
+        match self {
+            Self::True => Self::False,
+            Self::Unknown => Self::Unknown,
+            Self::False => Self::True,
+        }
+    }
+    /// The truth value of the conjunction.
+    pub fn and(self, other: Self) -> Self {
+        match [self, other] {
+            [Self::False, _] | [_, Self::False] => Self::False,
+            [Self::Unknown, _] | [_, Self::Unknown] => Self::Unknown,
+            [Self::True, Self::True] => Self::True,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::True => "true",
+            Self::Unknown => "unknown",
+            Self::False => "false",
+        }
+    }
 }
 
 //////////////////////////////////////////////////////
 
-impl ComplementKnowledge<'_> {
+impl<'a> ComplementKnowledge<'a> {
     fn contains(self, did: &DomainId, atom: &Atom) -> bool {
         match self {
             Self::Empty => false,
             Self::ComplementOf(k) => !k.contains(did, atom),
         }
     }
+    /// The knowledge this is the complement of, if any.
+    fn complemented(self) -> Option<&'a Knowledge> {
+        match self {
+            Self::Empty => None,
+            Self::ComplementOf(k) => Some(k),
+        }
+    }
 }
 
 impl VariableAssignments {
-    fn get_state_token(&self) -> StateToken {
+    pub(crate) fn get_state_token(&self) -> StateToken {
         StateToken { assignments_count: self.assignments.len() }
     }
-    fn restore_state(&mut self, state_token: StateToken) -> Result<(), ()> {
+    pub(crate) fn restore_state(&mut self, state_token: StateToken) -> Result<(), ()> {
         if state_token.assignments_count <= self.assignments.len() {
             self.assignments.truncate(state_token.assignments_count);
             Ok(())
@@ -196,7 +452,7 @@ impl VariableAssignments {
             Err(())
         }
     }
-    fn insert(&mut self, vid: &VariableId, atom2: Atom) -> Result<(), ()> {
+    pub(crate) fn insert(&mut self, vid: &VariableId, atom2: Atom) -> Result<(), ()> {
         if let Some(atom1) = self.get_mut(vid) {
             if atom1 == &atom2 {
                 Ok(())
@@ -214,7 +470,20 @@ impl VariableAssignments {
             .filter_map(|pair| if &pair.0 == vid { Some(&mut pair.1) } else { None })
             .next()
     }
-    fn get(&self, vid: &VariableId) -> Option<&Atom> {
+    /// Visits this, extended with `vid` bound to `atom`, if `vid` is not bound to another atom.
+    fn bind_and_visit(
+        &mut self,
+        vid: &VariableId,
+        atom: Atom,
+        visit: &mut impl FnMut(&mut VariableAssignments),
+    ) {
+        let state_token = self.get_state_token();
+        if self.insert(vid, atom).is_ok() {
+            visit(self)
+        }
+        self.restore_state(state_token).expect("token is from earlier");
+    }
+    pub(crate) fn get(&self, vid: &VariableId) -> Option<&Atom> {
         self.assignments
             .iter()
             .filter_map(|pair| if &pair.0 == vid { Some(&pair.1) } else { None })
@@ -226,19 +495,72 @@ impl Knowledge {
     pub fn is_empty(&self) -> bool {
         self.map.values().all(HashSet::is_empty)
     }
+    pub fn len(&self) -> usize {
+        self.map.values().map(HashSet::len).sum()
+    }
     pub fn atoms_in_domain(&self, did: &DomainId) -> impl Iterator<Item = &Atom> + '_ {
-        self.map.get(did).into_iter().flat_map(|set| set.iter())
+        self.map.get(did).into_iter().flat_map(|set| set.iter().map(Rc::as_ref))
+    }
+    /// The domains with any atoms.
+    pub fn domain_ids(&self) -> impl Iterator<Item = &DomainId> + '_ {
+        self.map.iter().filter(|(_did, set)| !set.is_empty()).map(|(did, _set)| did)
+    }
+    pub fn atoms(&self) -> impl Iterator<Item = &Atom> + '_ {
+        self.map.values().flat_map(|set| set.iter().map(Rc::as_ref))
+    }
+    pub fn len_in_domain(&self, did: &DomainId) -> usize {
+        self.map.get(did).map(HashSet::len).unwrap_or(0)
     }
     pub fn contains(&self, did: &DomainId, atom: &Atom) -> bool {
         self.map.get(did).map(|set| set.contains(atom)).unwrap_or(false)
     }
     pub fn insert(&mut self, did: &DomainId, atom: Atom) -> bool {
+        self.insert_shared(did, Rc::new(atom))
+    }
+    fn insert_shared(&mut self, did: &DomainId, atom: Rc<Atom>) -> bool {
+        if let (Some(indexes), Atom::Construct { args, .. }) = (self.indexes.get_mut(did), &*atom) {
+            if self.map.get(did).map(|set| set.contains(&atom)).unwrap_or(false) {
+                return false;
+            }
+            for (&position, index) in indexes.iter_mut() {
+                if let Some(arg) = args.get(position) {
+                    index.entry(arg.clone()).or_default().push(atom.clone());
+                }
+            }
+        }
         self.map.entry(did.clone()).or_default().insert(atom)
     }
-    pub fn absorb_disjoint(&mut self, other: &mut Self) {
-        for (did, set) in other.map.drain() {
+    /// Indexes the atoms of the given domain by their argument at the given position, unless already done.
+    /// Subsequent insertions keep the index up to date.
+    pub fn ensure_index(&mut self, did: &DomainId, position: usize) {
+        let Self { map, indexes } = self;
+        indexes.entry(did.clone()).or_default().entry(position).or_insert_with(|| {
+            let mut index = ArgumentIndex::default();
+            for atom in map.get(did).into_iter().flatten() {
+                if let Atom::Construct { args, .. } = &**atom {
+                    if let Some(arg) = args.get(position) {
+                        index.entry(arg.clone()).or_default().push(atom.clone());
+                    }
+                }
+            }
+            index
+        });
+    }
+    /// Atoms of the given domain whose argument at the given position is `arg`.
+    /// Returns `None` if no such index was built with `ensure_index`.
+    pub fn atoms_with_argument(
+        &self,
+        did: &DomainId,
+        position: usize,
+        arg: &Atom,
+    ) -> Option<impl Iterator<Item = &Atom> + '_> {
+        let index = self.indexes.get(did)?.get(&position)?;
+        Some(index.get(arg).into_iter().flatten().map(Rc::as_ref))
+    }
+    pub fn absorb_disjoint(&mut self, other: &Self) {
+        for (did, set) in other.map.iter() {
             for atom in set {
-                let new = self.insert(&did, atom);
+                let new = self.insert_shared(did, atom.clone());
                 assert!(new);
             }
         }
@@ -246,12 +568,24 @@ impl Knowledge {
 }
 
 impl Atom {
-    fn domain_id(&self) -> &DomainId {
+    pub fn domain_id(&self) -> &DomainId {
         match self {
             Self::Constant { c } => c.domain_id(),
             Self::Construct { did, .. } => did,
         }
     }
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Self::Constant { c: Constant::Int(value) } => Some(*value),
+            _ => None,
+        }
+    }
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Constant { c: Constant::Str(value) } => Some(value),
+            _ => None,
+        }
+    }
     fn uniquely_assign_variables(
         &self,
         ra: &RuleAtom,
@@ -269,7 +603,7 @@ impl Atom {
                     Err(())
                 }
             }
-            (Atom::Constant { c: c1 }, RuleAtom::Constant(c2)) => {
+            (Atom::Constant { c: c1 }, RuleAtom::Constant { c: c2, .. }) => {
                 if c1 == c2 {
                     Ok(())
                 } else {
@@ -292,11 +626,38 @@ impl Atom {
 }
 
 impl RuleAtom {
-    fn concretize(&self, va: &VariableAssignments) -> Result<Atom, ()> {
+    /// Binds the variables in this to match `atom`, if their types allow. Returns whether they match.
+    pub(crate) fn unify(
+        &self,
+        atom: &Atom,
+        v2d: &VariableTypes,
+        va: &mut VariableAssignments,
+    ) -> bool {
+        match (self, atom) {
+            (Self::Variable { vid, .. }, atom) => {
+                v2d.get(vid) == Some(atom.domain_id()) && va.insert(vid, atom.clone()).is_ok()
+            }
+            (Self::Constant { c: c1, .. }, Atom::Constant { c: c2 }) => c1 == c2,
+            (
+                Self::Construct { did: did1, args: args1, .. },
+                Atom::Construct { did: did2, args: args2 },
+            ) => {
+                did1 == did2
+                    && args1.len() == args2.len()
+                    && args1.iter().zip(args2).all(|(ra, atom)| ra.unify(atom, v2d, va))
+            }
+            _ => false,
+        }
+    }
+    /// The atom this denotes, if it has no variables.
+    pub fn ground(&self) -> Option<Atom> {
+        self.concretize(&VariableAssignments::default()).ok()
+    }
+    pub(crate) fn concretize(&self, va: &VariableAssignments) -> Result<Atom, ()> {
         match self {
             RuleAtom::Variable { vid, .. } => va.get(vid).ok_or(()).cloned(),
-            RuleAtom::Constant(c) => Ok(Atom::Constant { c: c.clone() }),
-            RuleAtom::Construct { args, did } => Ok(Atom::Construct {
+            RuleAtom::Constant { c, .. } => Ok(Atom::Constant { c: c.clone() }),
+            RuleAtom::Construct { args, did, .. } => Ok(Atom::Construct {
                 args: args.iter().map(|ra| ra.concretize(va)).collect::<Result<Vec<_>, _>>()?,
                 did: did.clone(),
             }),
@@ -304,110 +665,324 @@ impl RuleAtom {
     }
 }
 
+impl Condition {
+    /// Visits each extension of `va` under which this holds. Where it binds a variable (see
+    /// `Condition::bind`), there is one extension for each value; otherwise, `va` is unchanged.
+    /// Conditions with undefined values (e.g., divisions by zero) do not hold, even if negated.
+    pub(crate) fn evaluate(
+        &self,
+        scope: AggregateScope,
+        va: &mut VariableAssignments,
+        visit: &mut impl FnMut(&mut VariableAssignments),
+    ) {
+        match self {
+            Self::Compare { left, op, right, .. } => {
+                if op == &Comparison::Eq {
+                    for (side, other) in [(left, right), (right, left)] {
+                        if let Some(vid) = side.as_variable().filter(|vid| va.get(vid).is_none()) {
+                            if let Some(value) = other.value(scope, va) {
+                                va.bind_and_visit(vid, value, visit);
+                            }
+                            return;
+                        }
+                    }
+                }
+                if let (Some(left), Some(right)) = (left.value(scope, va), right.value(scope, va)) {
+                    if op.holds(left.cmp(&right)) {
+                        visit(va)
+                    }
+                }
+            }
+            Self::Builtin { sign, predicate, args, .. } => {
+                if let (Sign::Pos, Predicate::Split, [string, separator, piece]) =
+                    (sign, predicate, args.as_slice())
+                {
+                    if let Some(vid) = piece.as_variable().filter(|vid| va.get(vid).is_none()) {
+                        let [string, separator] =
+                            [string, separator].map(|arg| arg.value(scope, va));
+                        let (Some(string), Some(separator)) = (string, separator) else { return };
+                        let (Some(string), Some(separator)) = (string.as_str(), separator.as_str())
+                        else {
+                            return;
+                        };
+                        if separator.is_empty() {
+                            return;
+                        }
+                        for piece in string.split(separator) {
+                            let value = Atom::Constant { c: Constant::Str(piece.to_owned()) };
+                            va.bind_and_visit(vid, value, visit);
+                        }
+                        return;
+                    }
+                }
+                let values =
+                    args.iter().map(|arg| arg.value(scope, va)).collect::<Option<Vec<_>>>();
+                let strs = values.as_ref().and_then(|values| {
+                    values.iter().map(Atom::as_str).collect::<Option<Vec<&str>>>()
+                });
+                let holds = strs.and_then(|strs| predicate.holds(&strs));
+                if holds == Some(sign == &Sign::Pos) {
+                    visit(va)
+                }
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// The value of this under `va`, unless it is undefined, or has unbound variables.
+    fn value(&self, scope: AggregateScope, va: &VariableAssignments) -> Option<Atom> {
+        let int = |value| Atom::Constant { c: Constant::Int(value) };
+        let str = |value| Atom::Constant { c: Constant::Str(value) };
+        match self {
+            Self::Atom(ra) => ra.concretize(va).ok(),
+            Self::Arithmetic { op, args } => {
+                let [left, right] = args.as_ref();
+                op.apply(left.value(scope, va)?.as_int()?, right.value(scope, va)?.as_int()?)
+                    .map(int)
+            }
+            Self::Call { function, args, .. } => {
+                let values =
+                    args.iter().map(|arg| arg.value(scope, va)).collect::<Option<Vec<_>>>()?;
+                match function {
+                    Function::Min => values.into_iter().min(),
+                    Function::Max => values.into_iter().max(),
+                    Function::Concat => {
+                        let strs = values.iter().map(Atom::as_str).collect::<Option<Vec<_>>>()?;
+                        Some(str(strs.concat()))
+                    }
+                    Function::Length => {
+                        let length = values.first()?.as_str()?.chars().count();
+                        Some(int(length as i64))
+                    }
+                    Function::Lowercase => Some(str(values.first()?.as_str()?.to_lowercase())),
+                }
+            }
+            Self::Aggregate(aggregate) => aggregate.value(scope, va),
+        }
+    }
+}
+
+impl Aggregate {
+    /// The value of this over the truths of `scope`, given the bindings of its variables that
+    /// occur outside it. Undefined if the truths are not complete.
+    fn value(&self, scope: AggregateScope, va: &VariableAssignments) -> Option<Atom> {
+        fn satisfy(
+            literals: &[&RuleLiteral],
+            v2d: &VariableTypes,
+            truths: &Knowledge,
+            va: &mut VariableAssignments,
+            visit: &mut impl FnMut(&VariableAssignments),
+        ) {
+            let Some((literal, rest)) = literals.split_first() else {
+                return visit(va);
+            };
+            match literal.ra.concretize(va) {
+                Ok(atom) => {
+                    if truths.contains(atom.domain_id(), &atom) == (literal.sign == Sign::Pos) {
+                        satisfy(rest, v2d, truths, va, visit)
+                    }
+                }
+                Err(()) => {
+                    // positive, as negative literals are reached once their variables are bound
+                    let did = literal.ra.domain_id(v2d).expect("static checked");
+                    for atom in truths.atoms_in_domain(did) {
+                        let state_token = va.get_state_token();
+                        if literal.ra.unify(atom, v2d, va) {
+                            satisfy(rest, v2d, truths, va, visit);
+                        }
+                        va.restore_state(state_token).expect("token is from earlier");
+                    }
+                }
+            }
+        }
+        let truths = scope.truths?;
+        let [positive, negative] = [Sign::Pos, Sign::Neg]
+            .map(|sign| self.literals.iter().filter(move |literal| literal.sign == sign));
+        let literals: Vec<&RuleLiteral> = positive.chain(negative).collect();
+        let mut tuples = HashSet::<Vec<Atom>>::default();
+        satisfy(&literals, scope.v2d, truths, &mut va.clone(), &mut |va| {
+            let tuple = self.terms.iter().map(|term| term.concretize(va));
+            tuples.insert(tuple.collect::<Result<_, ()>>().expect("static checked"));
+        });
+        let firsts = || tuples.iter().map(|tuple| tuple[0].as_int()).collect::<Option<Vec<_>>>();
+        let value = match self.aggregation {
+            Aggregation::Count => Some(tuples.len() as i64),
+            Aggregation::Sum => firsts()?.into_iter().try_fold(0, i64::checked_add),
+            Aggregation::Min => firsts()?.into_iter().min(),
+            Aggregation::Max => firsts()?.into_iter().max(),
+        };
+        value.map(|value| Atom::Constant { c: Constant::Int(value) })
+    }
+}
+
+impl Predicate {
+    /// Whether this holds of the given strings, unless it is undefined for them.
+    fn holds(self, args: &[&str]) -> Option<bool> {
+        match (self, args) {
+            (Self::StartsWith, [string, prefix]) => Some(string.starts_with(prefix)),
+            (Self::EndsWith, [string, suffix]) => Some(string.ends_with(suffix)),
+            (Self::Contains, [string, infix]) => Some(string.contains(infix)),
+            (Self::Split, [_, "", _]) => None,
+            (Self::Split, [string, separator, piece]) => {
+                Some(string.split(separator).any(|x| &x == piece))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Arithmetic {
+    /// The result of this operation, unless it is undefined or overflows.
+    fn apply(self, left: i64, right: i64) -> Option<i64> {
+        match self {
+            Self::Add => left.checked_add(right),
+            Self::Sub => left.checked_sub(right),
+            Self::Mul => left.checked_mul(right),
+            Self::Div => left.checked_div(right),
+            Self::Rem => left.checked_rem(right),
+        }
+    }
+}
+
+impl Comparison {
+    /// Whether this holds of two values, given how they are ordered.
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Eq => ordering == Ordering::Equal,
+            Self::Ne => ordering != Ordering::Equal,
+            Self::Lt => ordering == Ordering::Less,
+            Self::Le => ordering != Ordering::Greater,
+            Self::Gt => ordering == Ordering::Greater,
+            Self::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl InferenceContext<'_> {
+    /// Which atoms the positive antecedent at the given index ranges over.
+    /// With semi-naive evaluation, antecedent `delta_idx` ranges over only the newest atoms,
+    /// and earlier antecedents exclude them, such that no combination is visited twice.
+    fn atom_source(&self, antecedent_idx: usize) -> AtomSource {
+        match self.delta_idx {
+            None => AtomSource::All,
+            Some(delta_idx) => match antecedent_idx.cmp(&delta_idx) {
+                Ordering::Less => AtomSource::Old,
+                Ordering::Equal => AtomSource::Delta,
+                Ordering::Greater => AtomSource::All,
+            },
+        }
+    }
+    /// Atoms of the given domain and source that may match the antecedent at the given index.
+    fn candidates<'b>(
+        &'b self,
+        did: &'b DomainId,
+        antecedent_idx: usize,
+        lookup: Lookup,
+        ra: &RuleAtom,
+        va: &VariableAssignments,
+    ) -> impl Iterator<Item = &'b Atom> + 'b {
+        let source = self.atom_source(antecedent_idx);
+        let knowledge = match source {
+            AtomSource::Delta => self.delta,
+            AtomSource::Old | AtomSource::All => self.pos_r,
+        };
+        let (mut whole, mut indexed) = (None, None);
+        match (lookup, ra) {
+            (Lookup::Whole, _) => {
+                let atom = ra.concretize(va).expect("bound");
+                whole = knowledge.map.get(did).and_then(|set| set.get(&atom)).map(Rc::as_ref);
+            }
+            (Lookup::Argument(position), RuleAtom::Construct { args, .. }) => {
+                let arg = args[position].concretize(va).expect("bound");
+                indexed = knowledge.atoms_with_argument(did, position, &arg);
+            }
+            _ => {}
+        }
+        // fall back to scanning if no index exists
+        let scanned = match lookup {
+            Lookup::Whole => None,
+            Lookup::Scan | Lookup::Argument(_) => {
+                indexed.is_none().then(|| knowledge.atoms_in_domain(did))
+            }
+        };
+        let iter = whole.into_iter().chain(indexed.into_iter().flatten());
+        iter.chain(scanned.into_iter().flatten()).filter(move |atom| match source {
+            AtomSource::Old => !self.delta.contains(did, atom),
+            AtomSource::Delta | AtomSource::All => true,
+        })
+    }
+}
+
 impl Rule {
     fn inference_stage(
         &self,
-        v2d: &VariableTypes,
-        neg: ComplementKnowledge,
-        pos_r: &Knowledge,
+        ctx: &InferenceContext,
+        plan: &JoinPlan,
         pos_w: &mut Knowledge,
         va: &mut VariableAssignments,
         visit_inserted: &mut impl FnMut(&VariableAssignments, &RuleAtom, &Vec<RuleLiteral>),
-        executable_config: &ExecutableConfig,
     ) {
-        self.inference_stage_rec(
-            v2d,
-            neg,
-            pos_r,
-            pos_w,
-            va,
-            visit_inserted,
-            &self.antecedents,
-            executable_config,
-        );
+        self.join_rec(ctx, va, &plan.steps, &mut |va| {
+            // all antecedents are satisfied
+            for consequent in self.consequents.iter() {
+                let atom = consequent.concretize(va).expect("should work");
+                if ctx.executable_config.subconsequence {
+                    let mut subatoms = HashSet::<&Atom>::default();
+                    atom.collect_subatoms(&mut subatoms);
+                    for subatom in subatoms {
+                        let did = subatom.domain_id();
+                        if !ctx.pos_r.contains(did, subatom) && pos_w.insert(did, subatom.clone()) {
+                            visit_inserted(va, consequent, &self.antecedents);
+                        }
+                    }
+                } else {
+                    let did = atom.domain_id();
+                    if !ctx.pos_r.contains(did, &atom) && pos_w.insert(did, atom.clone()) {
+                        visit_inserted(va, consequent, &self.antecedents);
+                    }
+                }
+            }
+        });
         va.assignments.clear();
     }
 
-    fn inference_stage_rec(
+    /// Visits each extension of `va` that satisfies the antecedents joined by the remaining steps.
+    fn join_rec(
         &self,
-        v2d: &VariableTypes,
-        neg: ComplementKnowledge,
-        pos_r: &Knowledge,
-        pos_w: &mut Knowledge,
+        ctx: &InferenceContext,
         va: &mut VariableAssignments,
-        visit_inserted: &mut impl FnMut(&VariableAssignments, &RuleAtom, &Vec<RuleLiteral>),
-        tail: &[RuleLiteral],
-        executable_config: &ExecutableConfig,
+        tail: &[JoinStep],
+        visit_satisfied: &mut impl FnMut(&VariableAssignments),
     ) {
         match tail {
-            [] => {
-                // perform all checks
-                let checks_pass = self.antecedents.iter().all(|antecedent| {
-                    if antecedent.sign == Sign::Neg {
-                        let did = antecedent.ra.domain_id(v2d).expect("static checked");
-                        let atom = antecedent.ra.concretize(va).expect("should work");
-                        neg.contains(&did, &atom)
-                    } else {
-                        true
-                    }
-                });
-                if checks_pass {
-                    // all checks passed
-                    for consequent in self.consequents.iter() {
-                        let atom = consequent.concretize(va).expect("should work");
-                        if executable_config.subconsequence {
-                            let mut subatoms = HashSet::<&Atom>::default();
-                            atom.collect_subatoms(&mut subatoms);
-                            for subatom in subatoms {
-                                let did = subatom.domain_id();
-                                if !pos_r.contains(did, &subatom) {
-                                    if pos_w.insert(did, subatom.clone()) {
-                                        visit_inserted(va, consequent, &self.antecedents);
-                                    }
-                                }
-                            }
-                        } else {
-                            let did = atom.domain_id();
-                            if !pos_r.contains(did, &atom) {
-                                if pos_w.insert(did, atom.clone()) {
-                                    visit_inserted(va, consequent, &self.antecedents);
-                                }
-                            }
-                        }
+            [] => visit_satisfied(va),
+            [JoinStep::Match { antecedent_idx, lookup }, new_tail @ ..] => {
+                let ra = &self.antecedents[*antecedent_idx].ra;
+                let did = ra.domain_id(ctx.v2d).expect("BAD");
+                for atom in ctx.candidates(did, *antecedent_idx, *lookup, ra, va) {
+                    let state_token = va.get_state_token();
+                    if atom.uniquely_assign_variables(ra, va).is_ok() {
+                        self.join_rec(ctx, va, new_tail, visit_satisfied)
                     }
+                    va.restore_state(state_token).expect("oh no");
                 }
             }
-            [head, new_tail @ ..] => match head.sign {
-                Sign::Pos => {
-                    let did = head.ra.domain_id(v2d).expect("BAD");
-                    for atom in pos_r.atoms_in_domain(&did) {
-                        let state_token = va.get_state_token();
-                        if atom.uniquely_assign_variables(&head.ra, va).is_ok() {
-                            self.inference_stage_rec(
-                                v2d,
-                                neg,
-                                pos_r,
-                                pos_w,
-                                va,
-                                visit_inserted,
-                                new_tail,
-                                executable_config,
-                            )
-                        }
-                        va.restore_state(state_token).expect("oh no");
-                    }
+            [JoinStep::Check { antecedent_idx }, new_tail @ ..] => {
+                let ra = &self.antecedents[*antecedent_idx].ra;
+                let did = ra.domain_id(ctx.v2d).expect("static checked");
+                let atom = ra.concretize(va).expect("should work");
+                if ctx.neg.contains(did, &atom) {
+                    self.join_rec(ctx, va, new_tail, visit_satisfied)
                 }
-                Sign::Neg => self.inference_stage_rec(
-                    v2d,
-                    neg,
-                    pos_r,
-                    pos_w,
-                    va,
-                    visit_inserted,
-                    new_tail,
-                    executable_config,
-                ),
-            },
+            }
+            [JoinStep::Evaluate { condition_idx }, new_tail @ ..] => {
+                // aggregates range over the knowledge whose complement is negated
+                let scope = AggregateScope { v2d: ctx.v2d, truths: ctx.neg.complemented() };
+                self.conditions[*condition_idx].evaluate(scope, va, &mut |va| {
+                    self.join_rec(ctx, va, new_tail, visit_satisfied)
+                })
+            }
         }
     }
 }
@@ -415,10 +990,7 @@ impl Rule {
 impl std::fmt::Debug for Atom {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Self::Constant { c } => match c {
-                Constant::Int(c) => c.fmt(f),
-                Constant::Str(c) => c.fmt(f),
-            },
+            Self::Constant { c } => c.fmt(f),
             Self::Construct { did, args } => {
                 did.fmt(f)?;
                 if !args.is_empty() {
@@ -438,6 +1010,19 @@ impl std::fmt::Debug for Literal {
     }
 }
 
+/// Indexes are a cache, and are not cloned or compared.
+impl Clone for Knowledge {
+    fn clone(&self) -> Self {
+        Self { map: self.map.clone(), indexes: Default::default() }
+    }
+}
+impl PartialEq for Knowledge {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+impl Eq for Knowledge {}
+
 impl std::fmt::Debug for Knowledge {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         use crate::util::{map_snd, sorted_vec};
@@ -449,7 +1034,7 @@ impl std::fmt::Debug for Knowledge {
 
 impl std::fmt::Debug for Bare<&Knowledge> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let truths = crate::util::sorted_vec(self.0.map.values().flat_map(HashSet::iter));
+        let truths = crate::util::sorted_vec(self.0.atoms());
         f.debug_set().entries(truths).finish()
     }
 }
@@ -0,0 +1,687 @@
+use crate::{
+    dynamics::{
+        AggregateScope, Atom, ConcreteInference, Denotation, DenotationResult, Knowledge, Literal,
+        TruthValue, VariableAssignments,
+    },
+    util::CommaSep,
+    *,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+
+/// Explains why an atom is not true: each rule that could infer it, and how that rule fails.
+#[derive(Debug)]
+pub struct WhyNot {
+    pub atom: Atom,
+    pub truth_value: TruthValue,
+    /// One for each rule consequent that unifies with the atom. Empty if the atom is true.
+    pub rule_failures: Vec<RuleFailure>,
+}
+
+/// How a rule fails to infer some atom that unifies with one of its consequents.
+#[derive(Debug)]
+pub struct RuleFailure {
+    /// The rule, with its variables as bound by unifying its consequent with the atom.
+    pub rule: Rule,
+    /// Each distinct antecedent at which an attempt to satisfy the rule failed.
+    pub failed_antecedents: Vec<FailedAntecedent>,
+    /// Each distinct condition that did not hold, with its bound variables substituted.
+    pub failed_conditions: Vec<Condition>,
+}
+
+/// An antecedent that is not true, given the variables bound at the point of failure.
+#[derive(Debug)]
+pub struct FailedAntecedent {
+    /// The antecedent, with its bound variables substituted.
+    pub literal: RuleLiteral,
+    /// The truth value of the literal, if it is ground.
+    /// Otherwise, it is positive, and no truth matches it.
+    pub truth_value: Option<TruthValue>,
+    /// Why the atom of a ground, positive literal is not true, within the requested depth,
+    /// unless it is already being explained.
+    pub why_not: Option<WhyNot>,
+}
+
+/// Explains why an atom is unknown: it depends on a cycle of unknown atoms, at least one of which
+/// is negated. Its truth would be contradicted by such a cycle, as would its falsity.
+#[derive(Debug)]
+pub struct WhyUnknown {
+    pub atom: Atom,
+    /// Dependencies leading from the atom to the cycle. Empty if the atom is on the cycle.
+    pub path: Vec<Dependency>,
+    /// Dependencies forming the cycle, starting where the path ends. At least one is negative.
+    pub cycle: Vec<Dependency>,
+}
+
+/// An unknown atom depends on an unknown antecedent of a rule that would infer it.
+/// Every other antecedent of that rule is true or unknown.
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub atom: Atom,
+    /// The rule, with its variables bound.
+    pub rule: Rule,
+    /// The antecedent, whose atom is unknown.
+    pub antecedent: Literal,
+}
+
+/// A derivation of a true atom, down to facts. Sub-proofs of the same atom are shared, so this is
+/// a directed acyclic graph, rather than a tree.
+#[derive(Debug)]
+pub struct Proof {
+    /// Nodes are identified by their index. The root, proving the atom of interest, is first.
+    pub nodes: Vec<ProofNode>,
+}
+
+/// Proves one atom, relying on others.
+#[derive(Debug)]
+pub struct ProofNode {
+    pub atom: Atom,
+    pub step: ProofStep,
+}
+
+#[derive(Debug)]
+pub enum ProofStep {
+    /// The atom is true, as a rule inferred it from antecedents, each with its own proof.
+    /// Facts are inferred from no antecedents.
+    Inferred { antecedents: Vec<(Sign, usize)> },
+    /// The atom is false, as it has no derivation. Proves its negation.
+    Underivable,
+    /// The atom is true, but it was not inferred as a consequent, e.g., it is a subconsequent.
+    Unrecorded,
+}
+
+/// Read-only inputs to the search for failing antecedents of one rule.
+struct FailureSearch<'a> {
+    denotation: &'a Denotation<Knowledge>,
+    v2d: &'a VariableTypes,
+    /// Positive antecedents first, then conditions, then negative antecedents, such that
+    /// conditions can be evaluated, and negative antecedents are ground, when reached.
+    goals: Vec<Goal<'a>>,
+}
+
+/// An antecedent or condition of the rule being searched.
+#[derive(Copy, Clone)]
+enum Goal<'a> {
+    Literal(&'a RuleLiteral),
+    Condition(&'a Condition),
+}
+
+/// Where the search for failing antecedents failed.
+#[derive(Default)]
+struct Failures {
+    literals: HashSet<(RuleLiteral, Option<TruthValue>)>,
+    conditions: HashSet<Condition>,
+}
+
+/////////////////////////
+
+impl ExecutableProgram {
+    /// Explains why `atom` is not true in `denotation`, the denotation of this program.
+    /// Failing antecedents are explained in turn, recursively, up to the given depth.
+    pub fn why_not(&self, denotation: &Denotation<Knowledge>, atom: &Atom, depth: usize) -> WhyNot {
+        self.why_not_rec(denotation, atom, depth, &mut vec![])
+    }
+
+    fn why_not_rec(
+        &self,
+        denotation: &Denotation<Knowledge>,
+        atom: &Atom,
+        depth: usize,
+        stack: &mut Vec<Atom>,
+    ) -> WhyNot {
+        let truth_value = denotation.truth_value(atom);
+        let mut rule_failures = vec![];
+        if truth_value != TruthValue::True {
+            stack.push(atom.clone());
+            for AnnotatedRule { v2d, rule, .. } in self.annotated_rules.iter() {
+                for consequent in rule.consequents.iter() {
+                    let mut va = VariableAssignments::default();
+                    if !consequent.unify(atom, v2d, &mut va) {
+                        continue;
+                    }
+                    let search = FailureSearch::new(denotation, v2d, rule);
+                    let mut failures = Failures::default();
+                    search.search(&search.goals, &mut va, &mut failures);
+                    let mut failed_conditions: Vec<_> = failures.conditions.into_iter().collect();
+                    failed_conditions.sort();
+                    let mut failures: Vec<_> = failures.literals.into_iter().collect();
+                    failures.sort();
+                    let failed_antecedents = failures
+                        .into_iter()
+                        .map(|(literal, truth_value)| {
+                            let ground = match literal.sign {
+                                Sign::Pos => literal.ra.ground(),
+                                Sign::Neg => None,
+                            };
+                            let why_not = ground
+                                .filter(|atom| depth > 0 && !stack.contains(atom))
+                                .map(|atom| self.why_not_rec(denotation, &atom, depth - 1, stack));
+                            FailedAntecedent { literal, truth_value, why_not }
+                        })
+                        .collect();
+                    // the search restored the bindings of the consequent
+                    rule_failures.push(RuleFailure {
+                        rule: rule.substituted(&va),
+                        failed_antecedents,
+                        failed_conditions,
+                    })
+                }
+            }
+            stack.pop();
+        }
+        WhyNot { atom: atom.clone(), truth_value, rule_failures }
+    }
+}
+
+impl ExecutableProgram {
+    /// Explains why `atom` is unknown in `denotation`, the denotation of this program.
+    /// Returns `None` if the atom is not unknown, or if no cycle through negation is found.
+    pub fn why_unknown(
+        &self,
+        denotation: &Denotation<Knowledge>,
+        atom: &Atom,
+    ) -> Option<WhyUnknown> {
+        if denotation.truth_value(atom) != TruthValue::Unknown {
+            return None;
+        }
+        let mut not_false = denotation.truths.clone();
+        not_false.absorb_disjoint(&denotation.unknowns);
+        // breadth-first, find the dependencies of the atoms reachable from `atom`
+        let mut dependencies = HashMap::<Atom, Vec<Dependency>>::default();
+        let mut parents = HashMap::<Atom, Dependency>::default();
+        let mut order = vec![atom.clone()];
+        let mut queue = VecDeque::from([atom.clone()]);
+        while let Some(next) = queue.pop_front() {
+            let next_dependencies = self.dependencies(denotation, &not_false, &next);
+            for dependency in next_dependencies.iter() {
+                let depended = &dependency.antecedent.atom;
+                if depended != atom && !parents.contains_key(depended) {
+                    parents.insert(depended.clone(), dependency.clone());
+                    order.push(depended.clone());
+                    queue.push_back(depended.clone());
+                }
+            }
+            dependencies.insert(next, next_dependencies);
+        }
+        let path_to = |target: &Atom| {
+            let mut path = vec![];
+            let mut at = target;
+            while let Some(dependency) = parents.get(at) {
+                at = &dependency.atom;
+                path.push(dependency.clone());
+            }
+            path.reverse();
+            path
+        };
+        // find a negative dependency from which its dependent is reachable, nearest `atom` first
+        for from in order.iter() {
+            let negative = dependencies[from]
+                .iter()
+                .filter(|dependency| dependency.antecedent.sign == Sign::Neg);
+            for dependency in negative {
+                if let Some(back) = shortest_path(&dependencies, &dependency.antecedent.atom, from)
+                {
+                    let cycle = std::iter::once(dependency.clone()).chain(back).collect();
+                    return Some(WhyUnknown { atom: atom.clone(), path: path_to(from), cycle });
+                }
+            }
+        }
+        None
+    }
+
+    /// The dependencies of an unknown atom, via each rule instance that would infer it, and whose
+    /// antecedents are all true or unknown. With subconsequence, a rule infers the subatoms of its
+    /// consequents, too.
+    fn dependencies(
+        &self,
+        denotation: &Denotation<Knowledge>,
+        not_false: &Knowledge,
+        atom: &Atom,
+    ) -> Vec<Dependency> {
+        let mut dependencies = vec![];
+        for AnnotatedRule { v2d, rule, .. } in self.annotated_rules.iter() {
+            let mut visit = |inferred: &RuleAtom| {
+                let mut va = VariableAssignments::default();
+                if !inferred.unify(atom, v2d, &mut va) {
+                    return;
+                }
+                let search = FailureSearch::new(denotation, v2d, rule);
+                search.satisfy(&search.goals, not_false, &mut va, &mut |va| {
+                    for antecedent in rule.antecedents.iter() {
+                        let depended = antecedent.ra.concretize(va).expect("satisfied");
+                        if denotation.truth_value(&depended) == TruthValue::Unknown {
+                            dependencies.push(Dependency {
+                                atom: atom.clone(),
+                                rule: rule.substituted(va),
+                                antecedent: Literal {
+                                    sign: antecedent.sign.clone(),
+                                    atom: depended,
+                                },
+                            });
+                        }
+                    }
+                });
+            };
+            for consequent in rule.consequents.iter() {
+                if self.executable_config.subconsequence {
+                    consequent.visit_subatoms(&mut visit)
+                } else {
+                    visit(consequent)
+                }
+            }
+        }
+        dependencies
+    }
+}
+
+impl ExecutableProgram {
+    /// A proof of `atom`, given the result of computing this program's denotation.
+    /// Returns `None` if the atom is not true.
+    pub fn proof(&self, dr: &DenotationResult, atom: &Atom) -> Option<Proof> {
+        if dr.denotation.truth_value(atom) != TruthValue::True {
+            return None;
+        }
+        // the first inference of each truth only relies on truths inferred before it
+        let mut inferences = HashMap::<Atom, ConcreteInference>::default();
+        for inference in self.how(dr) {
+            inferences.entry(inference.consequent.clone()).or_insert(inference);
+        }
+        let mut proof = Proof { nodes: vec![] };
+        let mut node_of = HashMap::<Atom, usize>::default();
+        proof.prove(&inferences, &mut node_of, atom);
+        Some(proof)
+    }
+}
+
+impl Proof {
+    /// Adds the nodes proving `atom` (if not yet added), returning the index of its node.
+    fn prove(
+        &mut self,
+        inferences: &HashMap<Atom, ConcreteInference>,
+        node_of: &mut HashMap<Atom, usize>,
+        atom: &Atom,
+    ) -> usize {
+        if let Some(&node) = node_of.get(atom) {
+            return node;
+        }
+        let node = self.nodes.len();
+        node_of.insert(atom.clone(), node);
+        self.nodes.push(ProofNode { atom: atom.clone(), step: ProofStep::Unrecorded });
+        if let Some(inference) = inferences.get(atom) {
+            let antecedents = inference
+                .antecedents
+                .iter()
+                .map(|Literal { sign, atom }| {
+                    let antecedent = match sign {
+                        Sign::Pos => self.prove(inferences, node_of, atom),
+                        Sign::Neg => self.underivable(node_of, atom),
+                    };
+                    (sign.clone(), antecedent)
+                })
+                .collect();
+            self.nodes[node].step = ProofStep::Inferred { antecedents };
+        }
+        node
+    }
+
+    fn underivable(&mut self, node_of: &mut HashMap<Atom, usize>, atom: &Atom) -> usize {
+        *node_of.entry(atom.clone()).or_insert_with(|| {
+            self.nodes.push(ProofNode { atom: atom.clone(), step: ProofStep::Underivable });
+            self.nodes.len() - 1
+        })
+    }
+
+    fn fmt_node(
+        &self,
+        f: &mut Formatter,
+        node: usize,
+        indent: usize,
+        shown: &mut HashSet<usize>,
+    ) -> FmtResult {
+        let ProofNode { atom, step } = &self.nodes[node];
+        write!(f, "{:width$}{:?}", "", atom, width = 2 * indent)?;
+        if !shown.insert(node) {
+            return writeln!(f, ", proven above");
+        }
+        match step {
+            ProofStep::Inferred { antecedents } if antecedents.is_empty() => {
+                writeln!(f, ", a fact")
+            }
+            ProofStep::Inferred { antecedents } => {
+                let literals = antecedents.iter().map(|(sign, antecedent)| Literal {
+                    sign: sign.clone(),
+                    atom: self.nodes[*antecedent].atom.clone(),
+                });
+                let literals: Vec<_> = literals.collect();
+                writeln!(
+                    f,
+                    ", by `{:?} :- {:?}`",
+                    atom,
+                    CommaSep { iter: &literals, spaced: true }
+                )?;
+                for (sign, antecedent) in antecedents {
+                    match sign {
+                        Sign::Pos => self.fmt_node(f, *antecedent, indent + 1, shown)?,
+                        Sign::Neg => {
+                            let negated = &self.nodes[*antecedent].atom;
+                            let width = 2 * (indent + 1);
+                            writeln!(f, "{:width$}!{:?}, as it has no derivation", "", negated)?
+                        }
+                    }
+                }
+                Ok(())
+            }
+            ProofStep::Underivable => writeln!(f, ", which has no derivation"),
+            ProofStep::Unrecorded => writeln!(f, ", not inferred as a consequent"),
+        }
+    }
+}
+
+/// The fewest dependencies leading from `from` to `to`, if any, following only those given.
+fn shortest_path(
+    dependencies: &HashMap<Atom, Vec<Dependency>>,
+    from: &Atom,
+    to: &Atom,
+) -> Option<Vec<Dependency>> {
+    let mut parents = HashMap::<&Atom, &Dependency>::default();
+    let mut queue = VecDeque::from([from]);
+    while let Some(next) = queue.pop_front() {
+        if next == to {
+            let mut path = vec![];
+            let mut at = to;
+            while at != from {
+                let dependency = parents[at];
+                path.push(dependency.clone());
+                at = &dependency.atom;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for dependency in dependencies.get(next).into_iter().flatten() {
+            let depended = &dependency.antecedent.atom;
+            if depended != from && !parents.contains_key(depended) {
+                parents.insert(depended, dependency);
+                queue.push_back(depended);
+            }
+        }
+    }
+    None
+}
+
+impl<'a> FailureSearch<'a> {
+    fn new(denotation: &'a Denotation<Knowledge>, v2d: &'a VariableTypes, rule: &'a Rule) -> Self {
+        let [positive, negative] = [Sign::Pos, Sign::Neg]
+            .map(|sign| rule.antecedents.iter().filter(move |antecedent| antecedent.sign == sign));
+        let mut bound = rule.aggregate_bound_variables();
+        let mut goals = vec![];
+        for antecedent in positive {
+            antecedent.ra.variables(&mut bound);
+            goals.push(Goal::Literal(antecedent));
+        }
+        // any conditions that cannot be evaluated are only reached by rules failing static checks
+        let ordered = rule.evaluation_order(&mut bound);
+        let unordered = rule.conditions.iter().filter(|c| !ordered.contains(c)).collect::<Vec<_>>();
+        goals.extend(ordered.into_iter().chain(unordered).map(Goal::Condition));
+        goals.extend(negative.map(Goal::Literal));
+        Self { denotation, v2d, goals }
+    }
+
+    /// Aggregates range over the truths, which are complete, as programs with aggregates are
+    /// stratified.
+    fn aggregate_scope(&self) -> AggregateScope<'a> {
+        AggregateScope { v2d: self.v2d, truths: Some(&self.denotation.truths) }
+    }
+
+    /// Visits each extension of `va` under which the given goals are satisfied, with antecedents
+    /// true or unknown, i.e., positive ones are in `not_false`, and negative ones are not true.
+    fn satisfy(
+        &self,
+        goals: &[Goal],
+        not_false: &Knowledge,
+        va: &mut VariableAssignments,
+        visit: &mut impl FnMut(&VariableAssignments),
+    ) {
+        let Some((goal, rest)) = goals.split_first() else {
+            return visit(va);
+        };
+        let antecedent = match goal {
+            Goal::Literal(antecedent) => antecedent,
+            Goal::Condition(condition) => {
+                return condition.evaluate(self.aggregate_scope(), va, &mut |va| {
+                    self.satisfy(rest, not_false, va, visit)
+                });
+            }
+        };
+        match antecedent.ra.concretize(va) {
+            Ok(atom) => {
+                let truth_value = self.denotation.truth_value(&atom);
+                let satisfied = match antecedent.sign {
+                    Sign::Pos => truth_value != TruthValue::False,
+                    Sign::Neg => truth_value != TruthValue::True,
+                };
+                if satisfied {
+                    self.satisfy(rest, not_false, va, visit)
+                }
+            }
+            Err(()) => {
+                let did = antecedent.ra.domain_id(self.v2d).expect("static checked");
+                for atom in not_false.atoms_in_domain(did) {
+                    let state_token = va.get_state_token();
+                    if antecedent.ra.unify(atom, self.v2d, va) {
+                        self.satisfy(rest, not_false, va, visit);
+                    }
+                    va.restore_state(state_token).expect("token is from earlier");
+                }
+            }
+        }
+    }
+
+    /// Tries to satisfy the given goals, extending `va`, recording each at which it fails.
+    fn search(&self, goals: &[Goal], va: &mut VariableAssignments, failures: &mut Failures) {
+        let Some((goal, rest)) = goals.split_first() else {
+            // all antecedents are true
+            return;
+        };
+        let antecedent = match goal {
+            Goal::Literal(antecedent) => antecedent,
+            Goal::Condition(condition) => {
+                let mut held = false;
+                condition.evaluate(self.aggregate_scope(), va, &mut |va| {
+                    held = true;
+                    self.search(rest, va, failures)
+                });
+                if !held {
+                    failures.conditions.insert(condition.substituted(va));
+                }
+                return;
+            }
+        };
+        let fail = |va: &VariableAssignments, failures: &mut Failures, truth_value| {
+            let literal =
+                RuleLiteral { sign: antecedent.sign.clone(), ra: antecedent.ra.substituted(va) };
+            failures.literals.insert((literal, truth_value));
+        };
+        match antecedent.ra.concretize(va) {
+            Ok(atom) => {
+                let mut truth_value = self.denotation.truth_value(&atom);
+                if antecedent.sign == Sign::Neg {
+                    truth_value = truth_value.negated();
+                }
+                if truth_value == TruthValue::True {
+                    self.search(rest, va, failures)
+                } else {
+                    fail(va, failures, Some(truth_value))
+                }
+            }
+            Err(()) => {
+                // positive, as negative antecedents are reached only once their variables are bound
+                let did = antecedent.ra.domain_id(self.v2d).expect("static checked");
+                let mut matched = false;
+                for atom in self.denotation.truths.atoms_in_domain(did) {
+                    let state_token = va.get_state_token();
+                    if antecedent.ra.unify(atom, self.v2d, va) {
+                        matched = true;
+                        self.search(rest, va, failures);
+                    }
+                    va.restore_state(state_token).expect("token is from earlier");
+                }
+                if !matched {
+                    fail(va, failures, None)
+                }
+            }
+        }
+    }
+}
+
+impl RuleAtom {
+    /// This, with its bound variables replaced by their atoms.
+    fn substituted(&self, va: &VariableAssignments) -> Self {
+        match self {
+            Self::Variable { vid, span, .. } => match va.get(vid) {
+                Some(atom) => atom.to_rule_atom(*span),
+                None => self.clone(),
+            },
+            Self::Constant { .. } => self.clone(),
+            Self::Construct { did, args, span } => Self::Construct {
+                did: did.clone(),
+                args: args.iter().map(|arg| arg.substituted(va)).collect(),
+                span: *span,
+            },
+        }
+    }
+}
+
+impl Rule {
+    fn substituted(&self, va: &VariableAssignments) -> Self {
+        Self {
+            consequents: self.consequents.iter().map(|ra| ra.substituted(va)).collect(),
+            antecedents: self
+                .antecedents
+                .iter()
+                .map(|lit| RuleLiteral { sign: lit.sign.clone(), ra: lit.ra.substituted(va) })
+                .collect(),
+            conditions: self.conditions.iter().map(|c| c.substituted(va)).collect(),
+            span: self.span,
+        }
+    }
+}
+
+impl Condition {
+    fn substituted(&self, va: &VariableAssignments) -> Self {
+        let mut condition = self.clone();
+        for ra in condition.operands_mut() {
+            *ra = ra.substituted(va);
+        }
+        condition
+    }
+}
+
+impl Atom {
+    fn to_rule_atom(&self, span: Span) -> RuleAtom {
+        match self {
+            Self::Constant { c } => RuleAtom::Constant { c: c.clone(), span },
+            Self::Construct { did, args } => RuleAtom::Construct {
+                did: did.clone(),
+                args: args.iter().map(|arg| arg.to_rule_atom(span)).collect(),
+                span,
+            },
+        }
+    }
+}
+
+impl WhyNot {
+    fn fmt_indented(&self, f: &mut Formatter, indent: usize) -> FmtResult {
+        let pad = |f: &mut Formatter, indent: usize| write!(f, "{:width$}", "", width = 2 * indent);
+        pad(f, indent)?;
+        writeln!(f, "{:?} is {}", self.atom, self.truth_value.name())?;
+        if self.truth_value == TruthValue::True {
+            return Ok(());
+        }
+        if self.rule_failures.is_empty() {
+            pad(f, indent + 1)?;
+            return writeln!(f, "no rule has a consequent matching it");
+        }
+        for RuleFailure { rule, failed_antecedents, failed_conditions } in self.rule_failures.iter()
+        {
+            pad(f, indent + 1)?;
+            writeln!(f, "rule `{:?}` fails, as", rule)?;
+            for FailedAntecedent { literal, truth_value, why_not } in failed_antecedents {
+                pad(f, indent + 2)?;
+                match truth_value {
+                    Some(truth_value) => writeln!(f, "`{:?}` is {}", literal, truth_value.name())?,
+                    None => writeln!(f, "`{:?}` matches no truth", literal)?,
+                }
+                if let Some(why_not) = why_not {
+                    why_not.fmt_indented(f, indent + 3)?;
+                }
+            }
+            for condition in failed_conditions {
+                pad(f, indent + 2)?;
+                writeln!(f, "`{:?}` does not hold", condition)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for WhyNot {
+    /// Indented text, e.g.,
+    /// ```text
+    /// a(1) is false
+    ///   rule `a(1) :- b(1)` fails, as
+    ///     `b(1)` is false
+    ///       b(1) is false
+    ///         no rule has a consequent matching it
+    /// ```
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl Display for WhyUnknown {
+    /// E.g.,
+    /// ```text
+    /// mutex(1) is unknown, as it depends on itself through negation:
+    ///   mutex(1) depends on !mutex(2), by rule `mutex(1) :- !mutex(2)`
+    ///   mutex(2) depends on !mutex(1), by rule `mutex(2) :- !mutex(1)`
+    /// ```
+    /// Otherwise, the dependencies leading to the cycle precede it.
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let line = |f: &mut Formatter, indent: usize, dependency: &Dependency| {
+            let Dependency { atom, rule, antecedent } = dependency;
+            let pad = 2 * indent;
+            writeln!(f, "{:pad$}{:?} depends on {:?}, by rule `{:?}`", "", atom, antecedent, rule)
+        };
+        if self.path.is_empty() {
+            writeln!(f, "{:?} is unknown, as it depends on itself through negation:", self.atom)?;
+            for dependency in self.cycle.iter() {
+                line(f, 1, dependency)?;
+            }
+        } else {
+            writeln!(f, "{:?} is unknown, as it depends on a cycle through negation:", self.atom)?;
+            for dependency in self.path.iter() {
+                line(f, 1, dependency)?;
+            }
+            writeln!(f, "  where the cycle is")?;
+            for dependency in self.cycle.iter() {
+                line(f, 2, dependency)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Proof {
+    /// Indented text, with each atom followed by its antecedents, e.g.,
+    /// ```text
+    /// a(1), by `a(1) :- b(1), !c(1)`
+    ///   b(1), a fact
+    ///   !c(1), as it has no derivation
+    /// ```
+    /// An atom proven repeatedly has its proof shown only the first time.
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.fmt_node(f, 0, 0, &mut HashSet::default())
+    }
+}
@@ -0,0 +1,359 @@
+use crate::{
+    dynamics::{Answer, Atom, ConcreteInference, Denotation, Knowledge, Literal},
+    explain::{Proof, ProofNode, ProofStep},
+    load::Sources,
+    statics::{
+        Dependence, DependencyScc, DomainDependencyGraph, NegativeCycle, SealBreak, UnboundedValues,
+    },
+    *,
+};
+use std::fmt::{Display, Formatter, Result as FmtResult, Write as _};
+
+/// A JSON value. Object members are kept in the order they were added.
+/// Displayed compactly, or indented with the alternate flag, i.e., `{:#}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/////////////////////////
+
+impl Json {
+    pub fn object<K: Into<String>>(members: impl IntoIterator<Item = (K, Json)>) -> Self {
+        Self::Object(members.into_iter().map(|(key, value)| (key.into(), value)).collect())
+    }
+    pub fn str(s: impl Into<String>) -> Self {
+        Self::Str(s.into())
+    }
+    /// Adds a member to this object. Panics if this is not an object.
+    pub fn insert(&mut self, key: impl Into<String>, value: Json) {
+        match self {
+            Self::Object(members) => members.push((key.into(), value)),
+            _ => panic!("inserting a member into a non-object"),
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut Formatter, indent: Option<usize>) -> FmtResult {
+        let newline = |f: &mut Formatter, depth: usize| match indent {
+            Some(_) => write!(f, "\n{:width$}", "", width = 2 * depth),
+            None => Ok(()),
+        };
+        let depth = indent.unwrap_or(0);
+        let inner = indent.map(|depth| depth + 1);
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Str(s) => write_escaped(f, s),
+            Self::Array(elements) if elements.is_empty() => write!(f, "[]"),
+            Self::Object(members) if members.is_empty() => write!(f, "{{}}"),
+            Self::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, depth + 1)?;
+                    element.fmt_indented(f, inner)?;
+                }
+                newline(f, depth)?;
+                write!(f, "]")
+            }
+            Self::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, depth + 1)?;
+                    write_escaped(f, key)?;
+                    write!(f, ":")?;
+                    if indent.is_some() {
+                        write!(f, " ")?;
+                    }
+                    value.fmt_indented(f, inner)?;
+                }
+                newline(f, depth)?;
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_escaped(f: &mut Formatter, s: &str) -> FmtResult {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let indent = if f.alternate() { Some(0) } else { None };
+        self.fmt_indented(f, indent)
+    }
+}
+
+////////// SERIALIZERS //////////
+
+impl Atom {
+    /// E.g., `item("apple")` is `{"domain": "item", "args": [{"str": "apple"}]}`.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Self::Constant { c } => c.to_json(),
+            Self::Construct { did, args } => Json::object([
+                ("domain", Json::str(&did.0)),
+                ("args", Json::Array(args.iter().map(Atom::to_json).collect())),
+            ]),
+        }
+    }
+}
+
+impl Constant {
+    /// E.g., `3` is `{"int": 3}`, and `"apple"` is `{"str": "apple"}`.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Self::Int(i) => Json::object([("int", Json::Int(*i))]),
+            Self::Str(s) => Json::object([("str", Json::str(s))]),
+        }
+    }
+}
+
+impl Knowledge {
+    /// An object mapping each domain (in order) to an array of its atoms (in order).
+    pub fn to_json(&self) -> Json {
+        use crate::util::sorted_vec;
+        Json::object(sorted_vec(self.domain_ids()).into_iter().map(|did| {
+            let atoms = sorted_vec(self.atoms_in_domain(did));
+            (did.0.clone(), Json::Array(atoms.into_iter().map(Atom::to_json).collect()))
+        }))
+    }
+}
+
+impl Denotation<Knowledge> {
+    /// An object with members `truths`, `unknowns`, and `emissions`.
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("truths", self.truths.to_json()),
+            ("unknowns", self.unknowns.to_json()),
+            ("emissions", self.emissions.to_json()),
+        ])
+    }
+}
+
+impl Answer {
+    /// E.g., `{"bindings": {"X": {"int": 3}}, "truth_value": "unknown"}`.
+    pub fn to_json(&self) -> Json {
+        let bindings = self.bindings.iter().map(|(vid, atom)| (vid.0.clone(), atom.to_json()));
+        Json::object([
+            ("bindings", Json::object(bindings)),
+            ("truth_value", Json::str(self.truth_value.name())),
+        ])
+    }
+}
+
+impl Sign {
+    /// `"pos"` or `"neg"`.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Self::Pos => Json::str("pos"),
+            Self::Neg => Json::str("neg"),
+        }
+    }
+}
+
+impl ConcreteInference {
+    /// E.g., `b :- a, !c` is `{"consequent": b, "antecedents": [{"sign": "pos", "atom": a}, ..]}`.
+    pub fn to_json(&self) -> Json {
+        let antecedents = self.antecedents.iter().map(|Literal { sign, atom }| {
+            Json::object([("sign", sign.to_json()), ("atom", atom.to_json())])
+        });
+        Json::object([
+            ("consequent", self.consequent.to_json()),
+            ("antecedents", Json::Array(antecedents.collect())),
+        ])
+    }
+}
+
+impl Proof {
+    /// An object with member `nodes`, an array whose first element proves the atom of interest.
+    /// Each node has an `atom` and a `step`: `"inferred"` (with the `antecedents`, each a `sign`
+    /// and the index of its `node`), `"underivable"`, or `"unrecorded"`.
+    pub fn to_json(&self) -> Json {
+        let nodes = self.nodes.iter().map(|ProofNode { atom, step }| {
+            let mut json = Json::object([("atom", atom.to_json())]);
+            match step {
+                ProofStep::Inferred { antecedents } => {
+                    json.insert("step", Json::str("inferred"));
+                    let antecedents = antecedents.iter().map(|(sign, node)| {
+                        Json::object([("sign", sign.to_json()), ("node", Json::Int(*node as i64))])
+                    });
+                    json.insert("antecedents", Json::Array(antecedents.collect()));
+                }
+                ProofStep::Underivable => json.insert("step", Json::str("underivable")),
+                ProofStep::Unrecorded => json.insert("step", Json::str("unrecorded")),
+            }
+            json
+        });
+        Json::object([("nodes", Json::Array(nodes.collect()))])
+    }
+}
+
+impl DomainDependencyGraph<'_> {
+    /// An object with members `edges` (each with a `from` and `to` domain, and a `sign`), and
+    /// `sccs` (each with its `domains`, and whether it is `negative`), with dependencies first.
+    pub fn to_json(&self) -> Json {
+        let mut edges = vec![];
+        for (digraph, sign) in [(&self.positive, "pos"), (&self.negative, "neg")] {
+            edges.extend(digraph.edges().map(|[x, y]| {
+                Json::object([
+                    ("from", Json::str(&x.0)),
+                    ("to", Json::str(&y.0)),
+                    ("sign", Json::str(sign)),
+                ])
+            }));
+        }
+        let sccs = self.sccs.iter().map(|DependencyScc { domains, negative }| {
+            Json::object([
+                ("domains", Json::Array(domains.iter().map(|did| Json::str(&did.0)).collect())),
+                ("negative", Json::Bool(*negative)),
+            ])
+        });
+        Json::object([("edges", Json::Array(edges)), ("sccs", Json::Array(sccs.collect()))])
+    }
+    /// E.g., `{"stratified": true, "strata": [["a"], ["b", "c"]]}`, listing the domains of each
+    /// stratum in order, or `{"stratified": false, "cycle": [...]}`, with edges as in `to_json`.
+    pub fn stratification_json(&self) -> Json {
+        match self.strata() {
+            Ok(strata) => {
+                let mut strata_dids = Vec::<Vec<Json>>::default();
+                let sorted = crate::util::sorted_vec(strata.into_iter().map(|(did, s)| (s, did)));
+                for (stratum, did) in sorted {
+                    if strata_dids.len() <= stratum {
+                        strata_dids.resize_with(stratum + 1, Vec::default);
+                    }
+                    strata_dids[stratum].push(Json::str(&did.0));
+                }
+                Json::object([
+                    ("stratified", Json::Bool(true)),
+                    ("strata", Json::Array(strata_dids.into_iter().map(Json::Array).collect())),
+                ])
+            }
+            Err(NegativeCycle { edges }) => {
+                let edges = edges.iter().map(|(x, y, dependence)| {
+                    let sign = if dependence == &Dependence::Positive { "pos" } else { "neg" };
+                    Json::object([
+                        ("from", Json::str(&x.0)),
+                        ("to", Json::str(&y.0)),
+                        ("sign", Json::str(sign)),
+                        ("aggregate", Json::Bool(dependence == &Dependence::Aggregate)),
+                    ])
+                });
+                Json::object([
+                    ("stratified", Json::Bool(false)),
+                    ("cycle", Json::Array(edges.collect())),
+                ])
+            }
+        }
+    }
+}
+
+impl Span {
+    /// Null if this span was not parsed from `sources`.
+    pub fn to_json(&self, sources: &Sources) -> Json {
+        if self.line == 0 {
+            return Json::Null;
+        }
+        let file = sources.get(self.file).map(|source_file| source_file.name.as_str());
+        Json::object([
+            ("file", file.map(Json::str).unwrap_or(Json::Null)),
+            ("line", Json::Int(self.line.into())),
+            ("col", Json::Int(self.col.into())),
+            ("start", Json::Int(self.start.into())),
+            ("end", Json::Int(self.end.into())),
+        ])
+    }
+}
+
+impl StatementAt {
+    /// E.g., `{"part": "p"}`, or `{"statement": 3}` for statements outside any part.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Self::InPart { part_name } => Json::object([("part", Json::str(&part_name.0))]),
+            Self::AnonPart { statement_index } => {
+                Json::object([("statement", Json::Int(*statement_index as i64))])
+            }
+        }
+    }
+}
+
+impl SealBreak<'_> {
+    pub fn to_json(&self, sources: &Sources) -> Json {
+        Json::object([
+            ("domain", Json::str(&self.did.0)),
+            ("modifier", self.modifier.to_json()),
+            ("modifier_span", self.modifier_span.to_json(sources)),
+            ("sealer", self.sealer.to_json()),
+            ("sealer_span", self.sealer_span.to_json(sources)),
+        ])
+    }
+}
+
+impl UnboundedValues<'_> {
+    /// E.g., `{"variable": "N", "rule": "count(N) :- ...", "span": ...}`, where the span is
+    /// that of the condition computing the variable.
+    pub fn to_json(&self, sources: &Sources) -> Json {
+        Json::object([
+            ("variable", Json::str(&self.vid.0)),
+            ("rule", Json::str(format!("{:?}", self.rule))),
+            ("span", self.condition.span().to_json(sources)),
+        ])
+    }
+}
+
+impl ExecutableProgram {
+    /// An array of the domains that are used but undeclared, in order of their first use.
+    pub fn used_undeclared_json(&self, sources: &Sources) -> Json {
+        let mut used_undeclared: Vec<_> = self.used_undeclared.iter().collect();
+        used_undeclared.sort_by_key(|(did, span)| (span.file, span.start, *did));
+        Json::Array(
+            used_undeclared
+                .into_iter()
+                .map(|(did, span)| {
+                    Json::object([("domain", Json::str(&did.0)), ("span", span.to_json(sources))])
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Program {
+    /// An array of the uses of undefined parts.
+    pub fn depended_undefined_json(&self, sources: &Sources) -> Json {
+        Json::Array(
+            self.depended_undefined()
+                .map(|(part, part_name)| {
+                    Json::object([
+                        ("part", Json::str(&part.name.0)),
+                        ("undefined", Json::str(&part_name.0)),
+                        ("span", part.span.to_json(sources)),
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
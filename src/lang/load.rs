@@ -0,0 +1,151 @@
+use crate::{
+    diagnostic::{Diagnostic, Label},
+    *,
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+/// A source file, as it was read.
+pub struct SourceFile {
+    /// How the file is referred to in diagnostics, e.g., its path or `<stdin>`.
+    pub name: String,
+    /// Canonical path of the file, if it was read from the file system.
+    /// Imports are resolved relative to its directory, or the working directory otherwise.
+    pub path: Option<PathBuf>,
+    pub text: String,
+}
+
+/// The source files of a program, identified by their `FileId`.
+#[derive(Default)]
+pub struct Sources {
+    files: Vec<SourceFile>,
+}
+
+/// Tracks the progress of `Sources::load`.
+#[derive(Default)]
+struct Loading {
+    /// Files currently being loaded, each importing the next.
+    stack: Vec<FileId>,
+    /// Files whose programs have already been composed into the result.
+    loaded: HashSet<FileId>,
+    program: Program,
+}
+
+/////////////////////////
+
+impl Sources {
+    pub fn add(&mut self, name: String, path: Option<PathBuf>, text: String) -> FileId {
+        let path = path.map(|path| path.canonicalize().unwrap_or(path));
+        self.files.push(SourceFile { name, path, text });
+        self.files.len() as FileId - 1
+    }
+    /// Reads the file at `path`, unless it is already among the sources, e.g., under another name.
+    pub fn read_file(&mut self, path: &Path) -> Result<FileId, std::io::Error> {
+        if let Some(existing) = self.find_path(path) {
+            return Ok(existing);
+        }
+        let text = std::fs::read_to_string(path)?;
+        Ok(self.add(path.display().to_string(), Some(path.to_owned()), text))
+    }
+    /// The file read from the given path, as identified by its canonical path.
+    fn find_path(&self, path: &Path) -> Option<FileId> {
+        let canonical = path.canonicalize().ok()?;
+        self.iter().find(|(_, f)| f.path.as_ref() == Some(&canonical)).map(|(file, _)| file)
+    }
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file as usize)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files.iter().enumerate().map(|(file, source_file)| (file as FileId, source_file))
+    }
+
+    /// Parses the program in each file added so far, and all the files they (transitively) import,
+    /// composing them into one program. Each file is loaded once, even if imported repeatedly.
+    /// Fails on the first parse error, unreadable import, or cycle of imports.
+    pub fn load(&mut self) -> Result<Program, Diagnostic> {
+        let mut loading = Loading::default();
+        for file in 0..self.files.len() as FileId {
+            if !loading.loaded.contains(&file) {
+                self.load_rec(file, &mut loading)?;
+            }
+        }
+        Ok(loading.program)
+    }
+
+    fn load_rec(&mut self, file: FileId, loading: &mut Loading) -> Result<(), Diagnostic> {
+        let mut program = self.parse(file)?;
+        loading.stack.push(file);
+        for import in program.imports.iter() {
+            let imported = self.resolve(file, import)?;
+            if let Some(cycle_start) = loading.stack.iter().position(|&f| f == imported) {
+                let mut diagnostic = Diagnostic::error(
+                    "E0012",
+                    format!("import cycle through `{}`", self.files[imported as usize].name),
+                    Label::new(import.span, "imported here"),
+                );
+                let cycle = loading.stack[cycle_start..].iter().chain(Some(&imported));
+                for (&f, &g) in cycle.clone().zip(cycle.skip(1)) {
+                    let [f, g] = [f, g].map(|f| &self.files[f as usize].name);
+                    diagnostic = diagnostic.with_note(format!("`{}` imports `{}`", f, g));
+                }
+                return Err(diagnostic);
+            }
+            if !loading.loaded.contains(&imported) {
+                self.load_rec(imported, loading)?;
+            }
+        }
+        loading.stack.pop();
+        loading.loaded.insert(file);
+        // imports are resolved, and their paths are meaningless outside this file
+        program.imports.clear();
+        loading.program = std::mem::take(&mut loading.program).composed(program);
+        Ok(())
+    }
+
+    /// Parses the given file (ignoring its imports), locating its spans in it.
+    pub fn parse(&self, file: FileId) -> Result<Program, Diagnostic> {
+        let source = preprocessing::comments_removed(self.files[file as usize].text.clone());
+        let parsed = parse::all_consuming(parse::program)(&source);
+        match parsed {
+            Ok((_rest, mut program)) => {
+                program.visit_spans_mut(&mut |span| span.file = file);
+                Ok(program)
+            }
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                Err(Diagnostic::from_parse_error(file, &source, &e))
+            }
+            Err(nom::Err::Incomplete(_)) => Err(Diagnostic::error(
+                "E0001",
+                "syntax error: incomplete input",
+                Label::new(Span { file, ..Span::default() }, ""),
+            )),
+        }
+    }
+
+    /// Finds the file imported by `import` in `file`, reading it if it is not yet among the sources.
+    /// The imported file is named relative to the name of the importing file.
+    fn resolve(&mut self, file: FileId, import: &Import) -> Result<FileId, Diagnostic> {
+        let importer = &self.files[file as usize];
+        let dir = match &importer.path {
+            Some(path) => path.parent().map(Path::to_owned).unwrap_or_default(),
+            None => PathBuf::from("."),
+        };
+        let name_dir = Path::new(&importer.name).parent().unwrap_or(Path::new(""));
+        let name = name_dir.join(&import.path).display().to_string();
+        let path = dir.join(&import.path);
+        if let Some(existing) = self.find_path(&path) {
+            return Ok(existing);
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Ok(self.add(name, Some(path), text)),
+            Err(e) => Err(Diagnostic::error(
+                "E0013",
+                format!("cannot read imported file `{}`", name),
+                Label::new(import.span, "imported here"),
+            )
+            .with_note(e.to_string())),
+        }
+    }
+}
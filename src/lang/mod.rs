@@ -7,9 +7,30 @@ pub mod parse;
 /// Statics of Seaso, implementing the checking of well-formedness of programs, and assignment of types to variables.
 pub mod statics;
 
+/// Planning the order in which rule antecedents are joined, and how their atoms are looked up.
+pub mod plan;
+
 /// Dynamics of Seaso, implementing methods and defining types needed to compute the denotation of a checked program.`
 pub mod dynamics;
 
+/// Explaining the truth values of atoms in a denotation, e.g., why an atom is not true.
+pub mod explain;
+
+/// Diagnostics reporting errors and warnings, rendered with annotated snippets of the source.
+pub mod diagnostic;
+
+/// Source files, and loading programs from them by following their imports.
+pub mod load;
+
+/// Serializing denotations and warnings as JSON, for consumption by other tools.
+pub mod json;
+
+/// Rendering proofs and graphs in the Graphviz DOT language, for visualization.
+pub mod dot;
+
+/// Formatting programs in canonical source form.
+pub mod pretty;
+
 pub mod util;
 
 use crate::lang::util::VecSet;
@@ -21,6 +42,14 @@ use std::collections::{HashMap, HashSet};
 pub struct Program {
     pub anon_mod_statements: Vec<Statement>,
     pub parts: VecSet<Part>,
+    pub imports: Vec<Import>,
+}
+
+/// `import "path.seaso".` pulls in the program at the given path, relative to the importing file.
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub struct Import {
+    pub path: String,
+    pub span: Span,
 }
 
 #[derive(Hash, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -28,8 +57,25 @@ pub struct Part {
     pub name: PartName,
     pub uses: VecSet<PartName>,
     pub statements: VecSet<Statement>,
+    /// Spans the part's header, i.e., its name and uses.
+    pub span: Span,
 }
 
+/// A region of source text: the file, a byte range, and the (1-based) line and column where it starts.
+/// Spans never affect the equality, order, or hash of the syntax they annotate.
+/// Syntax that was not parsed (e.g., added in preprocessing) has the default span.
+#[derive(Clone, Copy, Default)]
+pub struct Span {
+    pub file: FileId,
+    pub start: u32,
+    pub end: u32,
+    pub line: u32,
+    pub col: u32,
+}
+
+/// Identifies a source file in `load::Sources`.
+pub type FileId = u32;
+
 /// Used (internally) to remember where and how constructors are defined.
 pub type DomainDefinitions = HashMap<DomainId, Vec<DomainId>>;
 
@@ -57,19 +103,30 @@ pub enum Constant {
 /// See `Atom` (defined in `dynamics.rs`) for the concretized version.
 #[derive(Clone, PartialEq, Hash, Eq, Ord, PartialOrd)]
 pub enum RuleAtom {
-    Variable { vid: VariableId, ascription: Option<DomainId> },
-    Constant(Constant),
-    Construct { did: DomainId, args: Vec<RuleAtom> },
+    Variable { vid: VariableId, ascription: Option<DomainId>, span: Span },
+    Constant { c: Constant, span: Span },
+    Construct { did: DomainId, args: Vec<RuleAtom>, span: Span },
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
 }
 
-/// One of five kinds of statement.
+/// One of six kinds of statement.
 #[derive(Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
-pub enum Statement {
+pub enum StatementKind {
     Decl(Vec<DomainId>),
-    Defn { did: DomainId, params: Vec<DomainId> },
+    Defn {
+        did: DomainId,
+        params: Vec<DomainId>,
+    },
     Rule(Rule),
     Seal(DomainId),
     Emit(DomainId),
+    /// Asks for the atoms satisfying the antecedents of a rule without consequents.
+    Query(Rule),
 }
 
 /// A logical implication rule with N conjunctive consequents and N conjunctive antecedents.
@@ -77,6 +134,114 @@ pub enum Statement {
 pub struct Rule {
     pub consequents: Vec<RuleAtom>,
     pub antecedents: Vec<RuleLiteral>,
+    /// Built-in conditions, which are evaluated rather than matched against known atoms.
+    pub conditions: Vec<Condition>,
+    pub span: Span,
+}
+
+/// Occurs among the antecedents of rules, but is evaluated rather than matched against atoms.
+#[derive(Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub enum Condition {
+    /// Compares two expressions of the same type, e.g., `X < Y + 1`. Values of any domain can be
+    /// compared with `=` and `!=`, but only integers and strings are ordered, the latter
+    /// lexicographically. With `=`, a lone variable on one side is bound to the value of the other.
+    Compare { left: Expr, op: Comparison, right: Expr, span: Span },
+    /// Applies a built-in predicate, e.g., `starts_with(S, "agent-")`, or its negation.
+    Builtin { sign: Sign, predicate: Predicate, args: Vec<Expr>, span: Span },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// An expression over the variables of a rule. All but atoms are integers or strings.
+#[derive(Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub enum Expr {
+    /// A variable, a constant, or a construct, e.g., `party(P)`.
+    Atom(RuleAtom),
+    Arithmetic {
+        op: Arithmetic,
+        args: Box<[Expr; 2]>,
+    },
+    Call {
+        function: Function,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    Aggregate(Aggregate),
+}
+
+/// Aggregates the distinct tuples of `terms` over the ways its literals hold, e.g.,
+/// `count{ T : plan(T, A) }`. Its variables occurring elsewhere in the rule are bound before it is
+/// evaluated. The rest are local to it, each bound by one of its positive literals.
+#[derive(Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub struct Aggregate {
+    pub aggregation: Aggregation,
+    /// Never empty.
+    pub terms: Vec<RuleAtom>,
+    pub literals: Vec<RuleLiteral>,
+    pub span: Span,
+}
+
+/// How the tuples of an aggregate are combined into an integer. Except for `Count`, the first term
+/// of each tuple is an integer, and the others only distinguish tuples.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub enum Aggregation {
+    /// The number of tuples.
+    Count,
+    /// The sum of the first terms, which is 0 for no tuples.
+    Sum,
+    /// The least first term, undefined for no tuples.
+    Min,
+    /// The greatest first term, undefined for no tuples.
+    Max,
+}
+
+/// Binary operators on integers. Division rounds toward zero, and the remainder has the sign of
+/// the dividend. Where the result is undefined (e.g., division by zero) or overflows, the
+/// condition does not hold.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub enum Arithmetic {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// Built-in functions, each defined for all arguments of the right types.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub enum Function {
+    /// The least of one or more integers.
+    Min,
+    /// The greatest of one or more integers.
+    Max,
+    /// One or more strings, joined.
+    Concat,
+    /// The number of characters of a string.
+    Length,
+    /// A string, in lower case.
+    Lowercase,
+}
+
+/// Built-in predicates on strings. The first argument is the string tested.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub enum Predicate {
+    /// `starts_with(S, P)`: string `S` starts with `P`.
+    StartsWith,
+    /// `ends_with(S, P)`: string `S` ends with `P`.
+    EndsWith,
+    /// `contains(S, P)`: string `S` contains `P`.
+    Contains,
+    /// `split(S, Sep, P)`: `P` is one of the pieces of `S` separated by the non-empty `Sep`.
+    /// If `P` is an unbound variable, it is bound to each piece in turn.
+    Split,
 }
 
 /// Positive or negative sign, used to negate atoms, forming literals. Newtype for clarity.
@@ -97,6 +262,7 @@ pub struct RuleLiteral {
 pub struct AnnotatedRule {
     pub v2d: VariableTypes,
     pub rule: Rule,
+    pub plans: plan::JoinPlans,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
@@ -108,30 +274,37 @@ pub enum StatementAt {
     AnonPart { statement_index: usize },
 }
 
+/// Each sealer and modifier is mapped to the span of (one of) its sealing or modifying statements.
 #[derive(Debug, Default)]
 pub struct DomainSealersModifiers {
-    pub sealers: HashSet<StatementAt>,
-    pub modifiers: HashSet<StatementAt>,
+    pub sealers: HashMap<StatementAt, Span>,
+    pub modifiers: HashMap<StatementAt, Span>,
 }
 
 #[derive(Debug)]
 pub struct EqClasses {
     pub representatives: HashMap<DomainId, DomainId>,
     pub representative_members: HashMap<DomainId, Vec<DomainId>>,
+    /// Maps each member to the span of the first declaration equating it with another domain.
+    pub member_spans: HashMap<DomainId, Span>,
 }
 
 #[derive(Debug, Default)]
 pub struct ExecutableConfig {
     pub(crate) subconsequence: bool,
+    /// Inference is abandoned once more atoms than this are inferred.
+    pub(crate) max_atoms: Option<usize>,
 }
 
 #[derive(Debug)]
 pub struct ExecutableProgram {
     // pub(crate) dd: DomainDefinitions,
     pub(crate) annotated_rules: Vec<AnnotatedRule>,
+    pub(crate) annotated_queries: Vec<AnnotatedRule>,
     pub(crate) emissive: HashSet<DomainId>,
     pub(crate) sealers_modifiers: HashMap<DomainId, DomainSealersModifiers>,
     // pub(crate) declared_undefined: HashSet<DomainId>,
-    pub(crate) used_undeclared: HashSet<DomainId>,
+    /// Maps each domain that is used but not declared to the span of its first use.
+    pub(crate) used_undeclared: HashMap<DomainId, Span>,
     pub(crate) executable_config: ExecutableConfig,
 }
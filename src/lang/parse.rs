@@ -2,11 +2,11 @@ use crate::lang::VecSet;
 use crate::*;
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{alphanumeric1, i64 as nomi64, multispace0, none_of, satisfy},
-    combinator::{map as nommap, opt, recognize, verify},
+    bytes::complete::{escaped_transform, is_not, tag, take_until, take_while_m_n},
+    character::complete::{alphanumeric1, i64 as nomi64, multispace0, satisfy},
+    combinator::{map as nommap, map_opt, opt, recognize, value, verify},
     error::ParseError,
-    multi::{many0, many0_count, many1, separated_list0},
+    multi::{many0, many0_count, many1, separated_list0, separated_list1},
     sequence::{delimited, pair, preceded, terminated, tuple},
 };
 pub type IResult<I, O, E = nom::error::VerboseError<I>> = Result<(I, O), nom::Err<E>>;
@@ -45,16 +45,30 @@ where
     separated_list0(wstag(","), inner)
 }
 
-pub fn list<'a, F: 'a, O: 'a, E: ParseError<&'a str> + 'a>(
-    inner: F,
-) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<O>, E>
+pub fn list<'a, F, O, E>(inner: F) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<O>, E>
 where
-    E: ParseError<&'a str>,
+    E: ParseError<&'a str> + 'a,
     F: FnMut(&'a str) -> IResult<&'a str, O, E> + 'a,
+    O: 'a,
 {
     delimited(wstag("("), commasep(inner), wstag(")"))
 }
 
+/// Like `inner`, but also returns the span of the input it consumed, ignoring leading whitespace.
+/// Until resolved by `program`, the span's `start` and `end` are measured from the end of the input.
+pub fn spanned<'a, F, O, E>(mut inner: F) -> impl FnMut(&'a str) -> IResult<&'a str, (O, Span), E>
+where
+    E: ParseError<&'a str>,
+    F: FnMut(&'a str) -> IResult<&'a str, O, E> + 'a,
+{
+    move |i| {
+        let (i, _) = multispace0(i)?;
+        let (rest, o) = inner(i)?;
+        let [start, end] = [i.len() as u32, rest.len() as u32];
+        Ok((rest, (o, Span { start, end, ..Span::default() })))
+    }
+}
+
 pub fn all_consuming<'a, F, O, E>(inner: F) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
 where
     E: ParseError<&'a str>,
@@ -69,40 +83,71 @@ pub fn program(i: &str) -> IResult<&str, Program> {
     enum X {
         Statements(Vec<Statement>),
         Part(Part),
+        Import(Import),
     }
     let sta = nommap(statements1, X::Statements);
     let par = nommap(part, X::Part);
+    let imp = nommap(import, X::Import);
     let f = |xs: Vec<X>| {
         let mut anon_mod_statements = Vec::<Statement>::default();
         let mut parts = VecSet::default();
+        let mut imports = vec![];
         for x in xs {
             match x {
                 X::Statements(s) => anon_mod_statements.extend(s),
                 X::Part(p) => drop(parts.insert(p)),
+                X::Import(i) => imports.push(i),
             }
         }
-        Program { anon_mod_statements, parts }
+        Program { anon_mod_statements, parts, imports }
     };
-    nommap(many0(alt((sta, par))), f)(i)
+    let (rest, mut program) = nommap(many0(alt((imp, sta, par))), f)(i)?;
+    program.visit_spans_mut(&mut span_resolver(i));
+    Ok((rest, program))
+}
+
+/// A rule atom given outside of any program, e.g., on the command line.
+pub fn queried_atom(i: &str) -> IResult<&str, RuleAtom> {
+    let (rest, mut ra) = rule_atom(i)?;
+    ra.visit_spans_mut(&mut span_resolver(i));
+    Ok((rest, ra))
+}
+
+/// Resolves spans recorded by `spanned` while parsing `i` to be relative to the start of `i`.
+fn span_resolver(i: &str) -> impl FnMut(&mut Span) + '_ {
+    let line_starts = crate::util::line_starts(i);
+    move |span| {
+        let [start, end] = [i.len() - span.start as usize, i.len() - span.end as usize];
+        span.start = start as u32;
+        span.end = (start + i[start..end].trim_end().len()) as u32;
+        span.locate(i, &line_starts);
+    }
+}
+
+pub fn import(i: &str) -> IResult<&str, Import> {
+    let p = terminated(spanned(preceded(wstag("import"), string)), wstag("."));
+    nommap(p, |(path, span)| Import { path, span })(i)
 }
 
 pub fn part(i: &str) -> IResult<&str, Part> {
     let name = preceded(wstag("part"), part_name);
     let uses = preceded(wstag(":"), commasep(part_name));
     let body = delimited(wstag("{"), statements0, wstag("}"));
-    let p = tuple((name, opt(uses), body));
-    nommap(p, |(name, maybe_uses, statements)| Part {
+    let p = pair(spanned(pair(name, opt(uses))), body);
+    nommap(p, |(((name, maybe_uses), span), statements)| Part {
         name,
         uses: VecSet::from_vec(maybe_uses.unwrap_or_default()),
         statements: VecSet::from_vec(statements),
+        span,
     })(i)
 }
 pub fn like_statements(i: &str) -> IResult<&str, Vec<Statement>> {
-    pub fn stmts1<'a, F: FnMut(&'a str) -> IResult<&'a str, Statement> + 'a>(
+    pub fn stmts1<'a, F: FnMut(&'a str) -> IResult<&'a str, StatementKind> + 'a>(
         string: &'a str,
         inner: F,
     ) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<Statement>> + 'a {
-        preceded(wstag(string), many0(terminated(inner, wstag("."))))
+        let statement = nommap(spanned(inner), |(kind, span)| Statement { kind, span });
+        preceded(wstag(string), many0(terminated(statement, wstag("."))))
     }
     alt((
         stmts1("decl", decl),
@@ -110,6 +155,8 @@ pub fn like_statements(i: &str) -> IResult<&str, Vec<Statement>> {
         stmts1("seal", seal),
         stmts1("emit", emit),
         stmts1("rule", rule),
+        stmts1("query", query),
+        stmts1("?-", query),
     ))(i)
 }
 pub fn statements0(i: &str) -> IResult<&str, Vec<Statement>> {
@@ -119,34 +166,65 @@ pub fn statements1(i: &str) -> IResult<&str, Vec<Statement>> {
     nommap(many1(like_statements), |x| x.into_iter().flatten().collect())(i)
 }
 
-pub fn decl(i: &str) -> IResult<&str, Statement> {
+pub fn decl(i: &str) -> IResult<&str, StatementKind> {
     let p = separated_list0(wstag("="), domain_id);
-    nommap(p, Statement::Decl)(i)
+    nommap(p, StatementKind::Decl)(i)
 }
-pub fn emit(i: &str) -> IResult<&str, Statement> {
-    nommap(domain_id, Statement::Emit)(i)
+pub fn emit(i: &str) -> IResult<&str, StatementKind> {
+    nommap(domain_id, StatementKind::Emit)(i)
 }
 
-pub fn seal(i: &str) -> IResult<&str, Statement> {
-    nommap(domain_id, Statement::Seal)(i)
+pub fn seal(i: &str) -> IResult<&str, StatementKind> {
+    nommap(domain_id, StatementKind::Seal)(i)
 }
 
-pub fn defn(i: &str) -> IResult<&str, Statement> {
+pub fn defn(i: &str) -> IResult<&str, StatementKind> {
     let p = pair(domain_id, opt(list(domain_id)));
-    nommap(p, |(did, maybe_params)| Statement::Defn {
+    nommap(p, |(did, maybe_params)| StatementKind::Defn {
         did,
         params: maybe_params.unwrap_or_default(),
     })(i)
 }
 
-pub fn rule(i: &str) -> IResult<&str, Statement> {
+pub fn rule(i: &str) -> IResult<&str, StatementKind> {
     let c = commasep(rule_atom);
-    let a = alt((
-        preceded(wstag(":-"), commasep(rule_literal)),
-        nommap(multispace0, |_| Vec::default()),
-    ));
-    nommap(pair(c, a), |(consequents, antecedents)| {
-        Statement::Rule(Rule { consequents, antecedents })
+    let a = alt((preceded(wstag(":-"), body), nommap(multispace0, |_| Body::default())));
+    nommap(spanned(pair(c, a)), |((consequents, Body { antecedents, conditions }), span)| {
+        StatementKind::Rule(Rule { consequents, antecedents, conditions, span })
+    })(i)
+}
+
+pub fn query(i: &str) -> IResult<&str, StatementKind> {
+    let body =
+        verify(body, |body: &Body| !body.antecedents.is_empty() || !body.conditions.is_empty());
+    nommap(spanned(body), |(Body { antecedents, conditions }, span)| {
+        StatementKind::Query(Rule { consequents: vec![], antecedents, conditions, span })
+    })(i)
+}
+
+/// The antecedents of a rule, and its conditions, which may be given in any order.
+#[derive(Default)]
+pub struct Body {
+    antecedents: Vec<RuleLiteral>,
+    conditions: Vec<Condition>,
+}
+
+pub fn body(i: &str) -> IResult<&str, Body> {
+    enum X {
+        Literal(RuleLiteral),
+        Condition(Condition),
+    }
+    // conditions are tried first, as their left sides may look like literals
+    let item = alt((nommap(condition, X::Condition), nommap(rule_literal, X::Literal)));
+    nommap(commasep(item), |xs| {
+        let mut body = Body::default();
+        for x in xs {
+            match x {
+                X::Literal(literal) => body.antecedents.push(literal),
+                X::Condition(condition) => body.conditions.push(condition),
+            }
+        }
+        body
     })(i)
 }
 
@@ -168,21 +246,51 @@ pub fn ascription(i: &str) -> IResult<&str, DomainId> {
     preceded(wstag(":"), domain_id)(i)
 }
 
-pub fn variable(i: &str) -> IResult<&str, RuleAtom> {
+pub fn variable_id(i: &str) -> IResult<&str, VariableId> {
     let some_vid =
         recognize(tuple((many0_count(tag("_")), satisfy(|c| c.is_ascii_uppercase()), id_suffix)));
     let vid = wsl(alt((some_vid, tag("_"))));
-    let variable_id = nommap(vid, |ident| VariableId(ident.to_owned()));
-    nommap(pair(variable_id, opt(ascription)), |(vid, ascription)| RuleAtom::Variable {
-        vid,
-        ascription,
+    nommap(vid, |ident| VariableId(ident.to_owned()))(i)
+}
+
+pub fn variable(i: &str) -> IResult<&str, RuleAtom> {
+    nommap(spanned(pair(variable_id, opt(ascription))), |((vid, ascription), span)| {
+        RuleAtom::Variable { vid, ascription, span }
     })(i)
 }
 
+/// A quoted string, with escapes `\"`, `\\`, `\n`, `\r`, `\t`, `\0`, and `\u{...}` (of one to six
+/// hex digits), or a raw string, without escapes, e.g., `r#"say "hi""#`. A raw string ends at the
+/// first quote followed by as many `#` as followed its `r`.
 pub fn string(i: &str) -> IResult<&str, String> {
-    nommap(delimited(wstag("\""), recognize(many0_count(none_of("\""))), tag("\"")), str::to_owned)(
-        i,
-    )
+    wsl(alt((raw_string, quoted_string)))(i)
+}
+
+fn quoted_string(i: &str) -> IResult<&str, String> {
+    let unicode = map_opt(
+        delimited(tag("u{"), take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit()), tag("}")),
+        |hex| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+    );
+    let escape = alt((
+        value('"', tag("\"")),
+        value('\\', tag("\\")),
+        value('\n', tag("n")),
+        value('\r', tag("r")),
+        value('\t', tag("t")),
+        value('\0', tag("0")),
+        unicode,
+    ));
+    // `escaped_transform` fails on the empty string
+    let content =
+        nommap(opt(escaped_transform(is_not("\"\\"), '\\', escape)), Option::unwrap_or_default);
+    delimited(tag("\""), content, tag("\""))(i)
+}
+
+fn raw_string(i: &str) -> IResult<&str, String> {
+    let (i, hashes) = delimited(tag("r"), recognize(many0_count(tag("#"))), tag("\""))(i)?;
+    let closing = format!("\"{}", hashes);
+    let (i, content) = terminated(take_until(closing.as_str()), tag(closing.as_str()))(i)?;
+    Ok((i, content.to_owned()))
 }
 
 pub fn part_name(i: &str) -> IResult<&str, PartName> {
@@ -193,20 +301,119 @@ pub fn part_name(i: &str) -> IResult<&str, PartName> {
 pub fn constant(i: &str) -> IResult<&str, RuleAtom> {
     let int_constant = nommap(nomi64, Constant::Int);
     let str_constant = nommap(string, Constant::Str);
-    nommap(wsl(alt((int_constant, str_constant))), RuleAtom::Constant)(i)
+    nommap(spanned(alt((int_constant, str_constant))), |(c, span)| RuleAtom::Constant { c, span })(
+        i,
+    )
 }
 
 pub fn construct(i: &str) -> IResult<&str, RuleAtom> {
     let f = |did: &DomainId| did.0 != "str" && did.0 != "int";
     let pair = pair(verify(domain_id, f), opt(list(rule_atom)));
-    nommap(pair, |(did, maybe_args)| RuleAtom::Construct {
+    nommap(spanned(pair), |((did, maybe_args), span)| RuleAtom::Construct {
         did,
         args: maybe_args.unwrap_or_default(),
+        span,
     })(i)
 }
 
 pub fn rule_atom(i: &str) -> IResult<&str, RuleAtom> {
-    alt((construct, variable, constant))(i)
+    // constants first, since raw strings begin like domain identifiers, e.g., `r"x"`
+    alt((constant, construct, variable))(i)
+}
+
+pub fn condition(i: &str) -> IResult<&str, Condition> {
+    let op = alt((
+        nommap(wstag("<="), |_| Comparison::Le),
+        nommap(wstag(">="), |_| Comparison::Ge),
+        nommap(wstag("!="), |_| Comparison::Ne),
+        nommap(wstag("<"), |_| Comparison::Lt),
+        nommap(wstag(">"), |_| Comparison::Gt),
+        nommap(wstag("="), |_| Comparison::Eq),
+    ));
+    let compare = nommap(spanned(tuple((expr, op, expr))), |((left, op, right), span)| {
+        Condition::Compare { left, op, right, span }
+    });
+    let predicate = map_opt(builtin_name, |name| {
+        Predicate::ALL.into_iter().find(|predicate| predicate.name() == name)
+    });
+    let builtin = nommap(
+        spanned(tuple((opt(wstag("!")), predicate, list(expr)))),
+        |((excl, predicate, args), span)| {
+            let sign = if excl.is_some() { Sign::Neg } else { Sign::Pos };
+            Condition::Builtin { sign, predicate, args, span }
+        },
+    );
+    alt((compare, builtin))(i)
+}
+
+/// The name of a built-in function or predicate, e.g., `min` or `starts_with`.
+fn builtin_name(i: &str) -> IResult<&str, &str> {
+    wsl(recognize(pair(satisfy(|c| c.is_ascii_lowercase()), id_suffix)))(i)
+}
+
+/// Sums of products, each operator associating to the left.
+pub fn expr(i: &str) -> IResult<&str, Expr> {
+    let op =
+        alt((nommap(wstag("+"), |_| Arithmetic::Add), nommap(wstag("-"), |_| Arithmetic::Sub)));
+    arithmetic(term, op)(i)
+}
+
+pub fn term(i: &str) -> IResult<&str, Expr> {
+    let op = alt((
+        nommap(wstag("*"), |_| Arithmetic::Mul),
+        nommap(wstag("/"), |_| Arithmetic::Div),
+        nommap(wstag("%"), |_| Arithmetic::Rem),
+    ));
+    arithmetic(factor, op)(i)
+}
+
+/// Operands separated by operators, e.g., `1 + X - 2`, associating to the left.
+fn arithmetic<'a>(
+    operand: fn(&'a str) -> IResult<&'a str, Expr>,
+    op: impl FnMut(&'a str) -> IResult<&'a str, Arithmetic> + 'a,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Expr> + 'a {
+    let p = pair(operand, many0(pair(op, operand)));
+    nommap(p, |(first, rest)| {
+        rest.into_iter()
+            .fold(first, |left, (op, right)| Expr::Arithmetic { op, args: Box::new([left, right]) })
+    })
+}
+
+pub fn factor(i: &str) -> IResult<&str, Expr> {
+    let function = map_opt(builtin_name, |name| {
+        Function::ALL.into_iter().find(|function| function.name() == name)
+    });
+    let call = nommap(spanned(pair(function, list(expr))), |((function, args), span)| Expr::Call {
+        function,
+        args,
+        span,
+    });
+    let atom = nommap(rule_atom, Expr::Atom);
+    let aggregate = nommap(aggregate, Expr::Aggregate);
+    alt((delimited(wstag("("), expr, wstag(")")), aggregate, call, atom))(i)
+}
+
+/// E.g., `count{ T : plan(T, A) }`. Variables among the terms cannot be ascribed types, as `:`
+/// separates the terms from the literals.
+pub fn aggregate(i: &str) -> IResult<&str, Aggregate> {
+    let aggregation = map_opt(builtin_name, |name| {
+        Aggregation::ALL.into_iter().find(|aggregation| aggregation.name() == name)
+    });
+    let variable = nommap(spanned(variable_id), |(vid, span)| RuleAtom::Variable {
+        vid,
+        ascription: None,
+        span,
+    });
+    let term = alt((construct, variable, constant));
+    let terms = separated_list1(wstag(","), term);
+    let literals = separated_list1(wstag(","), rule_literal);
+    let p = tuple((aggregation, wstag("{"), terms, wstag(":"), literals, wstag("}")));
+    nommap(spanned(p), |((aggregation, _, terms, _, literals, _), span)| Aggregate {
+        aggregation,
+        terms,
+        literals,
+        span,
+    })(i)
 }
 
 pub fn rule_literal(i: &str) -> IResult<&str, RuleLiteral> {
@@ -214,3 +421,89 @@ pub fn rule_literal(i: &str) -> IResult<&str, RuleLiteral> {
     let sign = if excl.is_some() { Sign::Neg } else { Sign::Pos };
     Ok((i, RuleLiteral { sign, ra }))
 }
+
+////////// SPANS //////////
+
+impl Program {
+    pub(crate) fn visit_spans_mut(&mut self, f: &mut impl FnMut(&mut Span)) {
+        for import in self.imports.iter_mut() {
+            f(&mut import.span);
+        }
+        let mut guard = self.parts.as_vec_mut();
+        for part in guard.as_mut() {
+            f(&mut part.span);
+            let mut guard = part.statements.as_vec_mut();
+            for statement in guard.as_mut() {
+                statement.visit_spans_mut(f);
+            }
+        }
+        for statement in self.anon_mod_statements.iter_mut() {
+            statement.visit_spans_mut(f);
+        }
+    }
+}
+impl Statement {
+    fn visit_spans_mut(&mut self, f: &mut impl FnMut(&mut Span)) {
+        f(&mut self.span);
+        if let StatementKind::Rule(rule) | StatementKind::Query(rule) = &mut self.kind {
+            f(&mut rule.span);
+            for condition in rule.conditions.iter_mut() {
+                condition.visit_spans_mut(f);
+            }
+            for ra in rule.root_atoms_mut() {
+                ra.visit_spans_mut(f);
+            }
+        }
+    }
+}
+impl RuleAtom {
+    pub(crate) fn visit_spans_mut(&mut self, f: &mut impl FnMut(&mut Span)) {
+        match self {
+            Self::Variable { span, .. } | Self::Constant { span, .. } => f(span),
+            Self::Construct { args, span, .. } => {
+                f(span);
+                for arg in args {
+                    arg.visit_spans_mut(f);
+                }
+            }
+        }
+    }
+}
+impl Condition {
+    /// Visits the spans of this, and of its calls and aggregates. Those of its operands (including
+    /// the atoms of its aggregates) are not visited, as they are root atoms of the rule.
+    fn visit_spans_mut(&mut self, f: &mut impl FnMut(&mut Span)) {
+        match self {
+            Self::Compare { left, right, span, .. } => {
+                f(span);
+                left.visit_spans_mut(f);
+                right.visit_spans_mut(f);
+            }
+            Self::Builtin { args, span, .. } => {
+                f(span);
+                for arg in args {
+                    arg.visit_spans_mut(f);
+                }
+            }
+        }
+    }
+}
+impl Expr {
+    fn visit_spans_mut(&mut self, f: &mut impl FnMut(&mut Span)) {
+        match self {
+            Self::Atom(_) => {}
+            Self::Aggregate(aggregate) => f(&mut aggregate.span),
+            Self::Arithmetic { args, .. } => {
+                for arg in args.iter_mut() {
+                    arg.visit_spans_mut(f);
+                }
+            }
+            Self::Call { args, span, .. } => {
+                f(span);
+                for arg in args {
+                    arg.visit_spans_mut(f);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::*;
+use std::collections::{HashMap, HashSet};
+
+/// The order in which the antecedents of a rule are joined. See `Rule::join_plan`.
+#[derive(Debug)]
+pub struct JoinPlan {
+    pub steps: Vec<JoinStep>,
+}
+
+/// The join plans of a rule, planned once, as the program is made executable.
+#[derive(Debug)]
+pub struct JoinPlans {
+    /// Joins all antecedents over all that is known.
+    pub all: JoinPlan,
+    /// Maps each positive antecedent (by index) to a plan joining it first, over just the atoms
+    /// inferred in the previous round of semi-naive evaluation.
+    pub delta: HashMap<usize, JoinPlan>,
+}
+
+#[derive(Debug)]
+pub enum JoinStep {
+    /// Enumerate the atoms matching the positive antecedent at `antecedent_idx`.
+    Match { antecedent_idx: usize, lookup: Lookup },
+    /// Check the negative antecedent at `antecedent_idx`, whose variables are all bound.
+    Check { antecedent_idx: usize },
+    /// Evaluate the condition at `condition_idx`, binding its variable, if it binds one.
+    Evaluate { condition_idx: usize },
+}
+
+/// How the atoms matching a positive antecedent are found.
+#[derive(Debug, Copy, Clone)]
+pub enum Lookup {
+    /// All atoms of the antecedent's domain are tried.
+    Scan,
+    /// Atoms are looked up by their argument at this position, which is bound.
+    Argument(usize),
+    /// The antecedent is ground, and is looked up as a whole.
+    Whole,
+}
+
+/////////////////////////
+
+impl AnnotatedRule {
+    pub fn new(v2d: VariableTypes, rule: Rule) -> Self {
+        let plans = rule.join_plans();
+        Self { v2d, rule, plans }
+    }
+    /// The domains and argument positions by which the plans of this rule look up the atoms
+    /// of all that is known. These must be indexed; see `Knowledge::ensure_index`.
+    pub(crate) fn indexed_arguments(&self) -> impl Iterator<Item = (&DomainId, usize)> + '_ {
+        let delta = self.plans.delta.iter().map(|(&delta_idx, plan)| (Some(delta_idx), plan));
+        let plans = std::iter::once((None, &self.plans.all)).chain(delta);
+        plans.flat_map(move |(delta_idx, plan)| {
+            plan.steps.iter().filter_map(move |step| match step {
+                JoinStep::Match { antecedent_idx, lookup: Lookup::Argument(position) }
+                    if Some(*antecedent_idx) != delta_idx =>
+                {
+                    let ra = &self.rule.antecedents[*antecedent_idx].ra;
+                    Some((ra.domain_id(&self.v2d).expect("static checked"), *position))
+                }
+                _ => None,
+            })
+        })
+    }
+}
+
+impl JoinPlans {
+    /// The plan where the antecedent at `delta_idx` (if any) ranges over the newest atoms.
+    pub fn get(&self, delta_idx: Option<usize>) -> &JoinPlan {
+        match delta_idx {
+            None => &self.all,
+            Some(delta_idx) => &self.delta[&delta_idx],
+        }
+    }
+}
+
+impl Rule {
+    fn join_plans(&self) -> JoinPlans {
+        let positive = self.antecedents.iter().enumerate();
+        let positive = positive.filter(|(_, antecedent)| antecedent.sign == Sign::Pos);
+        let delta = positive.map(|(idx, _)| (idx, self.join_plan(Some(idx))));
+        JoinPlans { all: self.join_plan(None), delta: delta.collect() }
+    }
+
+    /// Orders the antecedents of this rule for joining, starting with the one at `first` (if any).
+    /// Greedily, the next positive antecedent is the one with the cheapest lookup, given the
+    /// variables bound by its predecessors. Ties are broken by source order.
+    /// Negative antecedents are checked, and conditions evaluated, as soon as they can be.
+    fn join_plan(&self, mut first: Option<usize>) -> JoinPlan {
+        let mut bound = self.aggregate_bound_variables();
+        let mut pending: Vec<usize> = (0..self.antecedents.len()).collect();
+        let mut pending_conditions: Vec<usize> = (0..self.conditions.len()).collect();
+        let mut steps = Vec::with_capacity(pending.len() + pending_conditions.len());
+        loop {
+            loop {
+                let planned = steps.len();
+                pending.retain(|&antecedent_idx| {
+                    let antecedent = &self.antecedents[antecedent_idx];
+                    let checkable =
+                        antecedent.sign == Sign::Neg && antecedent.ra.is_bound_by(&bound);
+                    if checkable {
+                        steps.push(JoinStep::Check { antecedent_idx });
+                    }
+                    !checkable
+                });
+                // evaluating a condition may bind a variable, making more steps possible
+                pending_conditions.retain(|&condition_idx| {
+                    let evaluable = self.conditions[condition_idx].bind(&mut bound);
+                    if evaluable {
+                        steps.push(JoinStep::Evaluate { condition_idx });
+                    }
+                    !evaluable
+                });
+                if steps.len() == planned {
+                    break;
+                }
+            }
+            let cheapest = pending
+                .iter()
+                .enumerate()
+                .filter(|(_, &antecedent_idx)| self.antecedents[antecedent_idx].sign == Sign::Pos)
+                .map(|(pending_idx, &antecedent_idx)| {
+                    let lookup = self.antecedents[antecedent_idx].ra.lookup(&bound);
+                    ((first != Some(antecedent_idx), lookup.cost()), pending_idx, lookup)
+                })
+                .min_by_key(|(cost, ..)| *cost);
+            let Some((_, pending_idx, lookup)) = cheapest else { break };
+            let antecedent_idx = pending.remove(pending_idx);
+            self.antecedents[antecedent_idx].ra.variables(&mut bound);
+            steps.push(JoinStep::Match { antecedent_idx, lookup });
+            first = None;
+        }
+        // unreachable for rules whose variables are all enumerable
+        steps.extend(pending.into_iter().map(|antecedent_idx| JoinStep::Check { antecedent_idx }));
+        steps.extend(
+            pending_conditions
+                .into_iter()
+                .map(|condition_idx| JoinStep::Evaluate { condition_idx }),
+        );
+        JoinPlan { steps }
+    }
+}
+
+impl Lookup {
+    /// Ranks lookups by how many atoms they are expected to try.
+    fn cost(self) -> usize {
+        match self {
+            Self::Whole => 0,
+            Self::Argument(_) => 1,
+            Self::Scan => 2,
+        }
+    }
+}
+
+impl RuleAtom {
+    fn is_bound_by(&self, bound: &HashSet<VariableId>) -> bool {
+        let mut vids = HashSet::default();
+        self.variables(&mut vids);
+        vids.is_subset(bound)
+    }
+
+    /// How to find the atoms matching this (positive antecedent) atom, given the bound variables.
+    /// Where several arguments are bound, the first is looked up.
+    fn lookup(&self, bound: &HashSet<VariableId>) -> Lookup {
+        if self.is_bound_by(bound) {
+            return Lookup::Whole;
+        }
+        if let RuleAtom::Construct { args, .. } = self {
+            if let Some(position) = args.iter().position(|arg| arg.is_bound_by(bound)) {
+                return Lookup::Argument(position);
+            }
+        }
+        Lookup::Scan
+    }
+}
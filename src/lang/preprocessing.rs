@@ -7,6 +7,7 @@ trait VisitMut<T> {
     fn visit_mut<F: FnMut(&mut T)>(&mut self, f: &mut F);
 }
 
+#[derive(Default)]
 pub struct EqDomainIdGraph<'a> {
     sorted_dir_edges: VecSet<[&'a DomainId; 2]>,
 }
@@ -14,6 +15,8 @@ pub struct EqDomainIdGraph<'a> {
 #[derive(Debug)]
 pub struct EquatePrimitivesError<'a> {
     pub eq_class: &'a Vec<DomainId>,
+    /// Spans a declaration equating the primitive with another domain.
+    pub span: Span,
 }
 
 ////////////////
@@ -23,12 +26,13 @@ pub fn add_antecedent_variables_as_pos_literals(program: &mut Program) {
     for part in guard.as_mut() {
         let mut guard = part.statements.as_vec_mut();
         for statement in guard.as_mut() {
-            if let Statement::Rule(rule) = statement {
+            if let StatementKind::Rule(rule) = &mut statement.kind {
                 let consequent_vids = rule.consequent_variables();
                 for vid in consequent_vids {
                     if !rule.is_enumerable_variable(&vid) {
+                        let span = rule.variable_span(&vid);
                         rule.antecedents.push(RuleLiteral {
-                            ra: RuleAtom::Variable { vid, ascription: None },
+                            ra: RuleAtom::Variable { vid, ascription: None, span },
                             sign: Sign::Pos,
                         });
                     }
@@ -50,8 +54,8 @@ pub fn normalize_domain_id_formatting(program: &mut Program, localize: bool) {
                 // do this regardless of `localize`
                 did.0.retain(|c| !c.is_whitespace());
                 // do this only if `localize`
-                if localize && part.name.0 != "" && !did.0.contains("@") {
-                    did.0.push_str("@");
+                if localize && !part.name.0.is_empty() && !did.0.contains('@') {
+                    did.0.push('@');
                     did.0.push_str(&part.name.0);
                 }
             };
@@ -60,11 +64,6 @@ pub fn normalize_domain_id_formatting(program: &mut Program, localize: bool) {
     }
 }
 
-impl Default for EqDomainIdGraph<'_> {
-    fn default() -> Self {
-        Self { sorted_dir_edges: Default::default() }
-    }
-}
 impl<'a> EqDomainIdGraph<'a> {
     fn insert(&mut self, a: &'a DomainId, b: &'a DomainId) {
         if a != b {
@@ -83,7 +82,7 @@ impl<'a> EqDomainIdGraph<'a> {
             }
         }
     }
-    fn to_equivalence_classes(self) -> EqClasses {
+    fn into_equivalence_classes(self, member_spans: HashMap<DomainId, Span>) -> EqClasses {
         // collect all verts
         let mut unmarked = HashSet::default();
         for [a, b] in self.sorted_dir_edges.iter() {
@@ -116,34 +115,48 @@ impl<'a> EqDomainIdGraph<'a> {
             .collect();
 
         // done!
-        EqClasses { representatives, representative_members }
+        EqClasses { representatives, representative_members, member_spans }
     }
 }
 
 pub fn deanonymize_variables(program: &mut Program) {
+    fn deanonymize(statement: &mut Statement) {
+        if let StatementKind::Rule(r) | StatementKind::Query(r) = &mut statement.kind {
+            let mut next_idx = 0;
+            let mut clos = |vid: &mut VariableId| {
+                if vid.0 == "_" {
+                    *vid = VariableId(format!("V{}ANON", next_idx));
+                    next_idx += 1;
+                }
+            };
+            r.visit_mut(&mut clos);
+        }
+    }
     let mut guard = program.parts.as_vec_mut();
     for part in guard.as_mut() {
         let mut guard = part.statements.as_vec_mut();
         for statement in guard.as_mut() {
-            if let Statement::Rule(r) = statement {
-                let mut next_idx = 0;
-                let mut clos = |vid: &mut VariableId| {
-                    if vid.0 == "_" {
-                        *vid = VariableId(format!("V{}ANON", next_idx));
-                        next_idx += 1;
-                    }
-                };
-                r.visit_mut(&mut clos);
-            }
+            deanonymize(statement);
         }
     }
+    for statement in program.anon_mod_statements.iter_mut() {
+        deanonymize(statement);
+    }
+}
+
+impl VariableId {
+    /// Whether this is `_`, or was allocated for a `_` by `deanonymize_variables`.
+    pub fn is_anonymous(&self) -> bool {
+        let idx = self.0.strip_prefix('V').and_then(|s| s.strip_suffix("ANON"));
+        self.0 == "_" || idx.is_some_and(|idx| idx.parse::<usize>().is_ok())
+    }
 }
 
 impl VisitMut<DomainId> for RuleAtom {
     fn visit_mut<F: FnMut(&mut DomainId)>(&mut self, f: &mut F) {
         match self {
-            Self::Variable { .. } | Self::Constant(..) => {}
-            Self::Construct { did, args } => {
+            Self::Variable { .. } | Self::Constant { .. } => {}
+            Self::Construct { did, args, .. } => {
                 f(did);
                 for arg in args {
                     arg.visit_mut(f)
@@ -154,20 +167,20 @@ impl VisitMut<DomainId> for RuleAtom {
 }
 impl VisitMut<DomainId> for Statement {
     fn visit_mut<F: FnMut(&mut DomainId)>(&mut self, f: &mut F) {
-        match self {
-            Self::Rule(rule) => {
+        match &mut self.kind {
+            StatementKind::Rule(rule) | StatementKind::Query(rule) => {
                 for ra in rule.root_atoms_mut() {
                     ra.visit_mut(f)
                 }
             }
-            Self::Defn { did, params } => {
+            StatementKind::Defn { did, params } => {
                 f(did);
                 for param in params {
                     f(param)
                 }
             }
-            Self::Seal(did) | Self::Emit(did) => f(did),
-            Self::Decl(dids) => {
+            StatementKind::Seal(did) | StatementKind::Emit(did) => f(did),
+            StatementKind::Decl(dids) => {
                 for did in dids {
                     f(did)
                 }
@@ -196,62 +209,126 @@ impl VisitMut<VariableId> for RuleAtom {
                 }
             }
             Self::Variable { vid, .. } => f(vid),
-            Self::Constant(..) => {}
+            Self::Constant { .. } => {}
         }
     }
 }
 
-/// Strips substrings that follow '#' but precede '\n' or the end of the string.
-pub fn comments_removed(mut s: String) -> String {
-    #[derive(Copy, Clone)]
-    enum State {
-        Outside,
-        LineComment,
-        BlockComment,
+/// Blanks out the comments of `s` (see `comment_ranges`). Each blanked character is replaced by spaces
+/// (of the same length in bytes), and newlines are kept, such that source positions are preserved.
+pub fn comments_removed(s: String) -> String {
+    let mut blanked = s.into_bytes();
+    for range in comment_ranges(std::str::from_utf8(&blanked).expect("was a string")) {
+        for byte in &mut blanked[range] {
+            if *byte != b'\n' {
+                *byte = b' ';
+            }
+        }
     }
-    use State::*;
-    let mut state = Outside;
-    s.retain(|c| {
-        let (new_state, retain) = match (state, c) {
-            (Outside, '#') => (LineComment, false),
-            (Outside, '<') => (BlockComment, false),
-            (LineComment, '\n') => (Outside, true),
-            (BlockComment, '>') => (Outside, false),
-            (Outside, _) => (Outside, true),
-            (s, _) => (
-                s,
-                match s {
-                    Outside => true,
-                    LineComment | BlockComment => false,
-                },
-            ),
-        };
-        state = new_state;
-        retain
-    });
-    s
+    String::from_utf8(blanked).expect("replaced whole characters")
+}
+
+/// Returns the byte ranges of the comments of `s`: substrings that follow '#' but precede '\n'
+/// or the end of the string, and substrings delimited by '<' and '>' (inclusive).
+/// Comment characters within string literals (see `parse::string`) do not begin comments.
+/// '<' begins a comment unless it follows an operand (ignoring whitespace and comments), i.e.,
+/// an identifier, integer, ')', string literal, or '}' closing an aggregate (e.g., `count{...}`),
+/// where it compares integers instead.
+pub fn comment_ranges(s: &str) -> Vec<std::ops::Range<usize>> {
+    let identifier_char = |c: char| c.is_alphanumeric() || "_-".contains(c);
+    let mut ranges = vec![];
+    let mut start = None::<(usize, char)>;
+    // whether the last character outside comments that is not whitespace ends an operand
+    let mut operand_ended = false;
+    // whether each enclosing '{' opens an aggregate, rather than a part
+    let mut braces = Vec::<bool>::new();
+    let mut aggregate_closed = false;
+    // the end of the last string literal
+    let mut string_end = 0;
+    for (i, c) in s.char_indices() {
+        if i < string_end {
+            continue;
+        }
+        match (start, c) {
+            (None, '"' | 'r') if string_literal_end(s, i).is_some() => {
+                string_end = string_literal_end(s, i).expect("just checked");
+                operand_ended = true;
+            }
+            (None, '#') => start = Some((i, c)),
+            (None, '<') if !operand_ended => start = Some((i, c)),
+            (None, c) if !c.is_whitespace() => {
+                match c {
+                    '{' => {
+                        let before = s[..i].trim_end();
+                        let word = before
+                            .rsplit(|c: char| !(c.is_alphanumeric() || "_-".contains(c)))
+                            .next();
+                        let aggregate = Aggregation::ALL.iter().any(|a| Some(a.name()) == word);
+                        braces.push(aggregate);
+                    }
+                    '}' => aggregate_closed = braces.pop().unwrap_or(false),
+                    _ => {}
+                }
+                operand_ended = match c {
+                    // e.g., not the `-` of `:-`
+                    '-' => s[..i].ends_with(|c: char| c != ':' && identifier_char(c)),
+                    ')' => true,
+                    '}' => aggregate_closed,
+                    c => identifier_char(c),
+                }
+            }
+            (Some((j, '#')), '\n') => {
+                ranges.push(j..i);
+                start = None;
+            }
+            (Some((j, '<')), '>') => {
+                ranges.push(j..i + 1);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((j, _)) = start {
+        ranges.push(j..s.len());
+    }
+    ranges
+}
+
+/// The end of the string literal starting at byte `i` of `s`, if any. A raw string's `r` does not
+/// follow an identifier character.
+pub fn string_literal_end(s: &str, i: usize) -> Option<usize> {
+    let identifier_char = |c: char| c.is_alphanumeric() || "_-".contains(c);
+    if s[i..].starts_with('r') && s[..i].ends_with(identifier_char) {
+        return None;
+    }
+    let (rest, _) = parse::string(&s[i..]).ok()?;
+    Some(s.len() - rest.len())
 }
 
 impl EqClasses {
     pub fn new(program: &Program) -> Self {
         let mut graph = EqDomainIdGraph::default();
+        let mut member_spans = HashMap::<DomainId, Span>::default();
         let iter = program
             .parts
             .iter()
             .flat_map(|part| part.statements.iter())
             .chain(program.anon_mod_statements.iter());
         for statement in iter {
-            if let Statement::Decl(vec) = statement {
+            if let StatementKind::Decl(vec) = &statement.kind {
                 for slice in vec.windows(2) {
                     if let [a, b] = slice {
                         graph.insert(a, b);
+                        for did in slice {
+                            member_spans.entry(did.clone()).or_insert(statement.span);
+                        }
                     } else {
                         unreachable!()
                     }
                 }
             }
         }
-        graph.to_equivalence_classes()
+        graph.into_equivalence_classes(member_spans)
     }
     pub fn normalize_equal_domain_ids_in(&self, statement: &mut Statement) {
         let mut clos = |did: &mut DomainId| {
@@ -263,6 +340,18 @@ impl EqClasses {
         };
         statement.visit_mut(&mut clos);
     }
+    /// Normalizes the domain ids of an atom given outside the program (e.g., a query),
+    /// like those of the program's statements.
+    pub fn normalize_query(&self, ra: &mut RuleAtom) {
+        ra.visit_mut(&mut |did: &mut DomainId| {
+            if !did.is_primitive() {
+                did.0.retain(|c| !c.is_whitespace());
+            }
+            if let Some(representative) = self.get_representative(did) {
+                *did = representative.clone();
+            }
+        });
+    }
     pub fn normalize_equal_domain_ids(&self, program: &mut Program) {
         let mut guard = program.parts.as_vec_mut();
         for part in guard.as_mut() {
@@ -275,7 +364,7 @@ impl EqClasses {
             self.normalize_equal_domain_ids_in(statement);
         }
     }
-    pub fn get_representative<'a, 'b>(&'a self, t: &'b DomainId) -> Option<&'a DomainId> {
+    pub fn get_representative<'a>(&'a self, t: &DomainId) -> Option<&'a DomainId> {
         self.representatives.get(t)
     }
     pub fn get_representatives(&self) -> &HashMap<DomainId, DomainId> {
@@ -284,12 +373,13 @@ impl EqClasses {
     pub fn get_representative_members(&self) -> &HashMap<DomainId, Vec<DomainId>> {
         &self.representative_members
     }
-    pub fn check_primitives(&self) -> Result<(), EquatePrimitivesError> {
+    pub fn check_primitives(&self) -> Result<(), EquatePrimitivesError<'_>> {
         for did in [DomainId::str(), DomainId::int()] {
             match self.get_representative(did) {
                 Some(representative) if representative != did && representative.is_primitive() => {
                     return Err(EquatePrimitivesError {
                         eq_class: self.representative_members.get(representative).unwrap(),
+                        span: self.member_spans.get(did).copied().unwrap_or_default(),
                     })
                 }
                 _ => {}
@@ -0,0 +1,99 @@
+use crate::{util::StringLiteral, *};
+use std::fmt::Write as _;
+
+/// Something printed on its own line(s) by `formatted`, located in the original source.
+struct Chunk {
+    start: usize,
+    end: usize,
+    text: String,
+    indented: bool,
+}
+
+/////////////////////////
+
+/// Formats the program parsed from `source` in canonical form: one statement per line, each with its
+/// own keyword, and statements of parts indented. Items keep their order in the source, as do comments,
+/// each of which is moved to its own line before the next item. Blank lines between items are kept,
+/// but not repeated.
+pub fn formatted(source: &str, program: &Program) -> String {
+    let mut top: Vec<(usize, Vec<Chunk>)> = vec![];
+    for import in program.imports.iter() {
+        let text = format!("import {:?}.", StringLiteral(&import.path));
+        top.push((import.span.start as usize, vec![Chunk::new(import.span, text, false)]));
+    }
+    for statement in program.anon_mod_statements.iter() {
+        let text = format!("{:?}.", statement);
+        top.push((statement.span.start as usize, vec![Chunk::new(statement.span, text, false)]));
+    }
+    for part in program.parts.iter() {
+        let mut header = format!("part {}", part.name.0);
+        let uses: Vec<&str> = part.uses.iter().map(|part_name| part_name.0.as_str()).collect();
+        if !uses.is_empty() {
+            write!(header, ": {}", uses.join(", ")).expect("writing to string");
+        }
+        let mut statements: Vec<&Statement> = part.statements.iter().collect();
+        statements.sort_by_key(|statement| statement.span.start);
+        let mut chunks = if statements.is_empty() {
+            vec![Chunk::new(part.span, header + " {}", false)]
+        } else {
+            let mut chunks = vec![Chunk::new(part.span, header + " {", false)];
+            for statement in statements {
+                chunks.push(Chunk::new(statement.span, format!("{:?}.", statement), true));
+            }
+            chunks
+        };
+        // the closing brace is placed after the last statement
+        if chunks.len() > 1 {
+            let last_end = chunks.last().expect("nonempty").end;
+            chunks.push(Chunk {
+                start: last_end,
+                end: last_end,
+                text: "}".into(),
+                indented: false,
+            });
+        }
+        top.push((part.span.start as usize, chunks));
+    }
+    top.sort_by_key(|(start, _)| *start);
+
+    // interleave the comments, each before the first item that follows it
+    let mut comments = preprocessing::comment_ranges(source).into_iter().peekable();
+    let mut chunks: Vec<Chunk> = vec![];
+    for chunk in top.into_iter().flat_map(|(_, chunks)| chunks) {
+        while let Some(range) = comments.next_if(|range| range.start < chunk.start) {
+            let text = source[range.clone()].trim_end().to_owned();
+            let indented = chunk.indented;
+            chunks.push(Chunk { start: range.start, end: range.end, text, indented });
+        }
+        chunks.push(chunk);
+    }
+    for range in comments {
+        let text = source[range.clone()].trim_end().to_owned();
+        chunks.push(Chunk { start: range.start, end: range.end, text, indented: false });
+    }
+
+    let mut s = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        if let Some(prev) = i.checked_sub(1).map(|i| &chunks[i]) {
+            let gap = source.get(prev.end..chunk.start).unwrap_or("");
+            let mut gap_lines = gap.split('\n');
+            let [_first, _last] = [gap_lines.next(), gap_lines.next_back()];
+            if gap_lines.any(|line| line.trim().is_empty()) {
+                s.push('\n');
+            }
+        }
+        // later lines of (block comment) chunks are kept verbatim
+        if chunk.indented {
+            s.push_str("    ");
+        }
+        s.push_str(&chunk.text);
+        s.push('\n');
+    }
+    s
+}
+
+impl Chunk {
+    fn new(span: Span, text: String, indented: bool) -> Self {
+        Self { start: span.start as usize, end: span.end as usize, text, indented }
+    }
+}
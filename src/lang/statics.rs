@@ -2,12 +2,103 @@ use crate::*;
 use core::hash::Hash;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     sync::OnceLock,
 };
 
 pub type PartUsageGraph<'a> = crate::util::Digraph<&'a PartName>;
 pub type ArgumentGraph<'a> = crate::util::Digraph<&'a DomainId>;
+use crate::util::Digraph;
+
+/// How domains depend on each other through rules: each consequent domain of a rule (including
+/// subconsequent domains, if they are inferred) depends on each of its antecedent domains.
+/// Edges point from the antecedent domain to the consequent domain.
+#[derive(Debug)]
+pub struct DomainDependencyGraph<'a> {
+    /// Edges of positive antecedents. Every domain is a vertex of this graph.
+    pub positive: Digraph<&'a DomainId>,
+    /// Edges of negative antecedents, and of the literals of aggregates, which likewise require
+    /// their domains to be complete.
+    pub negative: Digraph<&'a DomainId>,
+    /// Edges of the literals of aggregates. Subgraph of `negative`.
+    pub aggregated: Digraph<&'a DomainId>,
+    /// The strongly connected components, with each preceding those that depend on it.
+    pub sccs: Vec<DependencyScc<'a>>,
+}
+
+/// A maximal set of domains that all (transitively) depend on each other.
+#[derive(Debug)]
+pub struct DependencyScc<'a> {
+    /// Sorted domains.
+    pub domains: Vec<&'a DomainId>,
+    /// Whether any of its domains negatively depends on any other (or itself).
+    /// Only here can computing the denotation produce unknowns.
+    pub negative: bool,
+}
+
+/// Why termination is uncertain: a cycle in the argument graph, whose edges lead from the domain
+/// of each constructor in a rule consequent to the domain of each variable among its arguments.
+/// Rules may thus infer ever larger atoms.
+#[derive(Debug)]
+pub struct UnboundedDomainCycle<'a> {
+    /// The last edge ends where the first begins.
+    pub edges: Vec<ArgumentEdge<'a>>,
+}
+
+/// Why termination is uncertain, though not ruled out: a rule computes a value with a condition
+/// (e.g., `N = M + 1`), and infers it in a domain depending on an antecedent domain of the rule,
+/// such that rules may infer ever new values.
+#[derive(Debug)]
+pub struct UnboundedValues<'a> {
+    pub rule: &'a Rule,
+    /// The condition computing the value (or one it was computed from).
+    pub condition: &'a Condition,
+    pub vid: VariableId,
+    /// The consequent containing the value.
+    pub consequent: &'a RuleAtom,
+    /// A positive antecedent whose domain depends on that of the consequent.
+    pub antecedent: &'a RuleAtom,
+}
+
+/// An edge in the argument graph, with (the first) rule that introduced it.
+#[derive(Debug)]
+pub struct ArgumentEdge<'a> {
+    pub outer: &'a DomainId,
+    pub inner: &'a DomainId,
+    pub rule: &'a Rule,
+    /// Locates the outer construct in the rule's consequent.
+    pub span: Span,
+}
+
+/// Why a program is not stratified: a cycle of dependencies between domains, at least one of
+/// which is negative.
+#[derive(Debug)]
+pub struct NegativeCycle<'a> {
+    /// Each edge goes from an antecedent domain to a consequent domain, and the last edge ends
+    /// where the first begins. The first edge is not positive.
+    pub edges: Vec<(&'a DomainId, &'a DomainId, Dependence)>,
+}
+
+/// How a consequent domain depends on an antecedent domain, i.e., an edge of the domain
+/// dependency graph.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dependence {
+    Positive,
+    Negative,
+    /// Via a literal of an aggregate, which, like negation, requires its domain to be complete.
+    Aggregate,
+}
+
+/// Why the aggregates of a program cannot be evaluated: it is not stratified, so the domains they
+/// range over are not complete before they are evaluated.
+#[derive(Debug)]
+pub struct UnstratifiedAggregate<'a> {
+    pub aggregate: &'a Aggregate,
+    /// The domain of the aggregate's literal where `cycle` starts, if it passes through the
+    /// aggregate. Otherwise, `cycle` is elsewhere, through negation.
+    pub did: Option<&'a DomainId>,
+    pub cycle: NegativeCycle<'a>,
+}
 
 /// Identifies which statements first seal and then modify which domain.
 #[derive(Eq, Hash, PartialEq)]
@@ -15,23 +106,81 @@ pub struct SealBreak<'a> {
     pub did: &'a DomainId,
     pub modifier: &'a StatementAt,
     pub sealer: &'a StatementAt,
+    pub modifier_span: Span,
+    pub sealer_span: Span,
 }
 
+/// Each span locates the offending rule atom.
 #[derive(Debug)]
 pub enum ExecutableRuleError {
-    RepeatedlyDefinedPart { part_name: PartName },
-    OneVariableTwoTypes { vid: VariableId, domains: [DomainId; 2] },
-    MistypedArgument { constructor: DomainId, expected: DomainId, got: DomainId },
-    VariableNotEnumerable(VariableId),
-    WrongArity { did: DomainId, param_count: usize, arg_count: usize },
-    NoTypes(VariableId),
+    RepeatedlyDefinedPart {
+        part_name: PartName,
+    },
+    /// The spans locate where the variable is given each of its types, respectively.
+    OneVariableTwoTypes {
+        vid: VariableId,
+        domains: [DomainId; 2],
+        spans: [Span; 2],
+    },
+    MistypedArgument {
+        constructor: DomainId,
+        expected: DomainId,
+        got: DomainId,
+        span: Span,
+    },
+    VariableNotEnumerable {
+        vid: VariableId,
+        span: Span,
+    },
+    WrongArity {
+        did: DomainId,
+        param_count: usize,
+        arg_count: usize,
+        span: Span,
+    },
+    NoTypes {
+        vid: VariableId,
+        span: Span,
+    },
+    MistypedOperand {
+        expected: DomainId,
+        got: DomainId,
+        span: Span,
+    },
+    UncomparableOperands {
+        did: DomainId,
+        op: Comparison,
+        span: Span,
+    },
+    /// `arity` is `None` for built-ins taking one or more arguments.
+    WrongBuiltinArity {
+        name: &'static str,
+        arity: Option<usize>,
+        arg_count: usize,
+        span: Span,
+    },
 }
 
 #[derive(Debug)]
 pub enum ExecutableError<'a> {
-    ConflictingDefinitions { statement_at: StatementAt, did: DomainId, params: [Vec<DomainId>; 2] },
-    DefiningPrimitive { statement_at: StatementAt, did: DomainId, params: &'a [DomainId] },
-    ExecutableRuleError { statement_at: StatementAt, rule: &'a Rule, err: ExecutableRuleError },
+    /// The spans locate the previous and the conflicting definition, respectively.
+    ConflictingDefinitions {
+        statement_at: StatementAt,
+        did: DomainId,
+        params: [&'a [DomainId]; 2],
+        spans: [Span; 2],
+    },
+    DefiningPrimitive {
+        statement_at: StatementAt,
+        did: DomainId,
+        params: &'a [DomainId],
+        span: Span,
+    },
+    ExecutableRuleError {
+        statement_at: StatementAt,
+        rule: &'a Rule,
+        err: Box<ExecutableRuleError>,
+    },
 }
 
 //////////////////
@@ -39,7 +188,8 @@ pub enum ExecutableError<'a> {
 impl Program {
     pub fn composed(mut self, other: Self) -> Self {
         self.anon_mod_statements.extend(other.anon_mod_statements);
-        self.parts.extend(other.parts.into_iter());
+        self.parts.extend(other.parts);
+        self.imports.extend(other.imports);
         self
     }
     pub fn statements_and_at(&self) -> impl Iterator<Item = (&Statement, StatementAt)> {
@@ -54,17 +204,17 @@ impl Program {
             });
         part_statements.chain(anon_mod_statements)
     }
-    pub fn repeatedly_defined_part(&self) -> Option<&PartName> {
-        let mut seen_so_far = HashSet::<&PartName>::default();
+    /// Of two parts with the same name, returns that which appears later in the source.
+    pub fn repeatedly_defined_part(&self) -> Option<&Part> {
+        let mut seen_so_far = HashMap::<&PartName, &Part>::default();
         for part in self.parts.iter() {
-            let name = &part.name;
-            if !seen_so_far.insert(name) {
-                return Some(name);
+            if let Some(prev) = seen_so_far.insert(&part.name, part) {
+                return Some(if prev.span.start > part.span.start { prev } else { part });
             }
         }
         None
     }
-    pub fn part_usage_graph(&self) -> PartUsageGraph {
+    pub fn part_usage_graph(&self) -> PartUsageGraph<'_> {
         let mut digraph = PartUsageGraph::default();
         for x in self.parts.iter() {
             digraph.insert_vert(&x.name);
@@ -74,15 +224,18 @@ impl Program {
                 }
             }
         }
-        digraph.transitively_close();
         digraph
     }
     pub fn depended_undefined_names(&self) -> impl Iterator<Item = &PartName> {
+        self.depended_undefined().map(|(_part, part_name)| part_name)
+    }
+    /// Pairs each part with each of the undefined parts it uses.
+    pub fn depended_undefined(&self) -> impl Iterator<Item = (&Part, &PartName)> {
         let defined: HashSet<_> = self.parts.iter().map(|part| &part.name).collect();
         self.parts
             .iter()
-            .flat_map(|part| part.uses.iter())
-            .filter(move |part_name| !defined.contains(part_name))
+            .flat_map(|part| part.uses.iter().map(move |part_name| (part, part_name)))
+            .filter(move |(_part, part_name)| !defined.contains(part_name))
     }
     pub fn depended_parts(&self) -> impl Iterator<Item = &PartName> {
         self.parts.iter().flat_map(|part| part.uses.iter())
@@ -91,29 +244,32 @@ impl Program {
     pub fn executable(
         &self,
         executable_config: ExecutableConfig,
-    ) -> Result<ExecutableProgram, ExecutableError> {
+    ) -> Result<ExecutableProgram, ExecutableError<'_>> {
         // pass 1: collect domain definitions
         let dd = {
             let mut dd = DomainDefinitions::default();
+            let mut first_definitions = HashMap::<&DomainId, (&Vec<DomainId>, Span)>::default();
             for (statement, statement_at) in self.statements_and_at() {
-                if let Statement::Defn { did, params } = statement {
+                if let StatementKind::Defn { did, params } = &statement.kind {
                     if did.is_primitive() {
                         return Err(ExecutableError::DefiningPrimitive {
                             statement_at: statement_at.clone(),
                             did: did.clone(),
                             params,
+                            span: statement.span,
                         });
                     }
-                    let prev = dd.insert(did.clone(), params.clone());
-                    if let Some(previous_params) = prev {
-                        if &previous_params != params {
-                            return Err(ExecutableError::ConflictingDefinitions {
-                                statement_at,
-                                did: did.clone(),
-                                params: [previous_params, params.clone()],
-                            });
-                        }
+                    let (previous_params, previous_span) =
+                        *first_definitions.entry(did).or_insert((params, statement.span));
+                    if previous_params != params {
+                        return Err(ExecutableError::ConflictingDefinitions {
+                            statement_at,
+                            did: did.clone(),
+                            params: [previous_params, params],
+                            spans: [previous_span, statement.span],
+                        });
                     }
+                    dd.insert(did.clone(), params.clone());
                 }
             }
             dd
@@ -121,20 +277,21 @@ impl Program {
 
         // pass 2: extract annotated rules from statements. collect usages, sealers, modifiers, ...
         let mut annotated_rules = vec![];
+        let mut annotated_queries = vec![];
         let mut sealers_modifiers = HashMap::<DomainId, DomainSealersModifiers>::default();
         let mut emissive = HashSet::<DomainId>::default();
         let mut declared = HashSet::<DomainId>::default();
-        let mut used = HashSet::<DomainId>::default();
+        let mut used = HashMap::<DomainId, Span>::default();
 
         for (statement, statement_at) in self.statements_and_at() {
-            match statement {
-                Statement::Rule(rule) => {
+            match &statement.kind {
+                StatementKind::Rule(rule) => {
                     rule.used_dids(&mut used);
                     let v2d = rule.rule_type_variables(&dd).map_err(|err| {
                         ExecutableError::ExecutableRuleError {
                             statement_at: statement_at.clone(),
                             rule,
-                            err,
+                            err: Box::new(err),
                         }
                     })?;
 
@@ -145,7 +302,8 @@ impl Program {
                                 .entry(did.clone())
                                 .or_default()
                                 .modifiers
-                                .insert(statement_at.clone());
+                                .entry(statement_at.clone())
+                                .or_insert(statement.span);
                         }
                     };
                     for consequent in &rule.consequents {
@@ -157,40 +315,59 @@ impl Program {
                         }
                     }
 
-                    annotated_rules.push(AnnotatedRule {
-                        v2d,
-                        rule: rule.clone().variable_ascriptions_cleared(),
-                    })
+                    annotated_rules
+                        .push(AnnotatedRule::new(v2d, rule.clone().variable_ascriptions_cleared()))
+                }
+                StatementKind::Query(rule) => {
+                    rule.used_dids(&mut used);
+                    let v2d = rule.rule_type_variables(&dd).map_err(|err| {
+                        ExecutableError::ExecutableRuleError {
+                            statement_at: statement_at.clone(),
+                            rule,
+                            err: Box::new(err),
+                        }
+                    })?;
+                    annotated_queries
+                        .push(AnnotatedRule::new(v2d, rule.clone().variable_ascriptions_cleared()))
                 }
-                Statement::Emit(did) => {
-                    used.insert(did.clone());
+                StatementKind::Emit(did) => {
+                    used.entry(did.clone()).or_insert(statement.span);
                     emissive.insert(did.clone());
                 }
-                Statement::Seal(did) => {
-                    used.insert(did.clone());
-                    sealers_modifiers.entry(did.clone()).or_default().sealers.insert(statement_at);
+                StatementKind::Seal(did) => {
+                    used.entry(did.clone()).or_insert(statement.span);
+                    sealers_modifiers
+                        .entry(did.clone())
+                        .or_default()
+                        .sealers
+                        .entry(statement_at)
+                        .or_insert(statement.span);
                 }
-                Statement::Decl(vec) => {
+                StatementKind::Decl(vec) => {
                     for did in vec {
                         declared.insert(did.clone());
                     }
                 }
-                Statement::Defn { params, did } => {
+                StatementKind::Defn { params, did } => {
                     // did a definition pass earlier
                     declared.insert(did.clone());
                     for param in params {
-                        used.insert(param.clone());
+                        used.entry(param.clone()).or_insert(statement.span);
                     }
                 }
             }
         }
 
-        let used_undeclared: HashSet<_> =
-            used.into_iter().filter(|did| !declared.contains(did) && !did.is_primitive()).collect();
+        annotated_queries.sort_by_key(|query| (query.rule.span.file, query.rule.span.start));
+        let used_undeclared: HashMap<_, _> = used
+            .into_iter()
+            .filter(|(did, _span)| !declared.contains(did) && !did.is_primitive())
+            .collect();
         // let declared_undefined = declared.into_iter().filter(|did| !dd.contains_key(did)).collect();
         Ok(ExecutableProgram {
             // dd,
             annotated_rules,
+            annotated_queries,
             emissive,
             sealers_modifiers,
             // declared_undefined,
@@ -201,13 +378,23 @@ impl Program {
 }
 
 impl<'a> PartUsageGraph<'a> {
-    fn would_break(&self, sealer: &StatementAt, modifier: &StatementAt) -> bool {
+    /// Given the parts reachable from each sealing part searched so far, which this extends.
+    fn would_break(
+        &self,
+        sealer: &'a StatementAt,
+        modifier: &StatementAt,
+        reachable: &mut HashMap<&'a PartName, BTreeSet<&'a PartName>>,
+    ) -> bool {
         use StatementAt::{AnonPart, InPart};
-        match [sealer, modifier] {
-            [InPart { part_name: a }, InPart { part_name: b }] => {
-                a != b && !self.contains_edge(&[a, b])
+        match (sealer, modifier) {
+            (InPart { part_name: a }, InPart { part_name: b }) if a != b => {
+                let reachable_from_a = reachable
+                    .entry(a)
+                    .or_insert_with(|| self.reachable(&a).into_iter().copied().collect());
+                !reachable_from_a.contains(b)
             }
-            [AnonPart { statement_index: a }, AnonPart { statement_index: b }] => a < b,
+            (InPart { .. }, InPart { .. }) => false,
+            (AnonPart { statement_index: a }, AnonPart { statement_index: b }) => a < b,
             _ => true,
         }
     }
@@ -216,14 +403,24 @@ impl<'a> PartUsageGraph<'a> {
         &'a self,
         ep: &'b ExecutableProgram,
     ) -> impl Iterator<Item = SealBreak<'a>> + 'a {
-        ep.sealers_modifiers.iter().flat_map(move |(did, dsm)| {
-            dsm.sealers.iter().flat_map(move |sealer| {
-                dsm.modifiers
-                    .iter()
-                    .filter(move |&modifier| self.would_break(sealer, modifier))
-                    .map(move |modifier| SealBreak { sealer, modifier, did })
-            })
-        })
+        let mut reachable = HashMap::default();
+        let mut breaks = vec![];
+        for (did, dsm) in ep.sealers_modifiers.iter() {
+            for (sealer, &sealer_span) in dsm.sealers.iter() {
+                for (modifier, &modifier_span) in dsm.modifiers.iter() {
+                    if self.would_break(sealer, modifier, &mut reachable) {
+                        breaks.push(SealBreak {
+                            sealer,
+                            modifier,
+                            did,
+                            sealer_span,
+                            modifier_span,
+                        });
+                    }
+                }
+            }
+        }
+        breaks.into_iter()
     }
 }
 
@@ -234,61 +431,328 @@ impl DomainId {
     }
 }
 
+/// Where each variable is first given its type. Used in error reporting.
+type VariableTypeSpans = HashMap<VariableId, Span>;
+
+/// Gives `vid` type `did` at `span`, failing if it already has another type.
+fn type_variable(
+    vid: &VariableId,
+    did: &DomainId,
+    span: Span,
+    vt: &mut VariableTypes,
+    vt_spans: &mut VariableTypeSpans,
+) -> Result<(), ExecutableRuleError> {
+    match vt.insert(vid.clone(), did.clone()) {
+        Some(did2) if did != &did2 => Err(ExecutableRuleError::OneVariableTwoTypes {
+            vid: vid.clone(),
+            domains: [did.clone(), did2],
+            spans: [span, vt_spans.get(vid).copied().unwrap_or_default()],
+        }),
+        _ => {
+            vt_spans.entry(vid.clone()).or_insert(span);
+            Ok(())
+        }
+    }
+}
+
+/// Maps each edge of an argument graph to the rule that first introduced it, and the span of the
+/// outer construct.
+type ArgumentEdgeRules<'a> = HashMap<[&'a DomainId; 2], (&'a Rule, Span)>;
+
 // adds [X,Y] to argument graph for each X-type construct containing Y-type variable.
-fn populate_argument_graph<'a, 'b>(
-    ag: &'a mut ArgumentGraph<'b>,
+fn populate_argument_graph<'b>(
+    ag: &mut ArgumentGraph<'b>,
+    edge_rules: &mut ArgumentEdgeRules<'b>,
     rule: &'b Rule,
     v2d: &'b VariableTypes,
 ) {
-    fn walk<'a, 'b, 'c>(
+    fn walk<'a>(
         ra: &'a RuleAtom,
-        ag: &'b mut ArgumentGraph<'a>,
+        ag: &mut ArgumentGraph<'a>,
+        edge_rules: &mut ArgumentEdgeRules<'a>,
+        rule: &'a Rule,
         v2d: &'a VariableTypes,
-        outer_dids: &'c mut Vec<&'a DomainId>,
+        outers: &mut Vec<(&'a DomainId, Span)>,
     ) {
         match ra {
-            RuleAtom::Constant(_) => {}
+            RuleAtom::Constant { .. } => {}
             RuleAtom::Variable { vid, .. } => {
                 let inner_did = v2d.get(vid).expect("WAH");
-                for &outer_did in outer_dids.iter() {
+                for &(outer_did, span) in outers.iter() {
                     ag.insert_edge([outer_did, inner_did]);
+                    edge_rules.entry([outer_did, inner_did]).or_insert((rule, span));
                 }
             }
-            RuleAtom::Construct { did, args } => {
-                outer_dids.push(did);
+            RuleAtom::Construct { did, args, span } => {
+                outers.push((did, *span));
                 for arg in args {
-                    walk(arg, ag, v2d, outer_dids);
+                    walk(arg, ag, edge_rules, rule, v2d, outers);
                 }
-                outer_dids.pop().unwrap();
+                outers.pop().unwrap();
             }
         }
     }
-    let mut outer_dids = vec![];
+    let mut outers = vec![];
     for consequent in rule.consequents.iter() {
-        walk(consequent, ag, v2d, &mut outer_dids)
+        walk(consequent, ag, edge_rules, rule, v2d, &mut outers)
+    }
+}
+
+impl<'a> DomainDependencyGraph<'a> {
+    /// Assigns each domain a stratum, such that each domain is in a stratum no lower than those
+    /// it depends on positively, and higher than those it depends on negatively. The strata are
+    /// as low as possible. Fails if no such assignment exists, i.e., the program is not
+    /// stratified, returning the first negative cycle.
+    pub fn strata(&self) -> Result<HashMap<&'a DomainId, usize>, NegativeCycle<'a>> {
+        if let Some(cycle) = self.negative_cycle() {
+            return Err(cycle);
+        }
+        // components are visited after those they depend on
+        let mut strata = HashMap::<&DomainId, usize>::default();
+        for scc in self.sccs.iter() {
+            let stratum =
+                scc.domains.iter().filter_map(|did| strata.get(did)).copied().max().unwrap_or(0);
+            for &did in scc.domains.iter() {
+                strata.insert(did, stratum);
+                for (digraph, step) in [(&self.positive, 0), (&self.negative, 1)] {
+                    for &dependent in digraph.successors(&did) {
+                        if scc.domains.binary_search(&dependent).is_err() {
+                            let bound = strata.entry(dependent).or_default();
+                            *bound = (*bound).max(stratum + step);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(strata)
+    }
+
+    /// A shortest cycle through the first negative edge of the first negative component.
+    pub fn negative_cycle(&self) -> Option<NegativeCycle<'a>> {
+        let scc = self.sccs.iter().find(|scc| scc.negative)?;
+        let (x, y) = scc
+            .domains
+            .iter()
+            .flat_map(|&x| self.negative.successors(&x).map(move |&y| (x, y)))
+            .find(|(_, y)| scc.domains.binary_search(y).is_ok())
+            .expect("negative component has a negative edge");
+        self.cycle_through(&self.union(), x, y)
+    }
+
+    /// A shortest cycle starting with the negative edge from `x` to `y`, if `y` reaches `x`.
+    /// `union` is that of this graph.
+    fn cycle_through(
+        &self,
+        union: &Digraph<&'a DomainId>,
+        x: &'a DomainId,
+        y: &'a DomainId,
+    ) -> Option<NegativeCycle<'a>> {
+        let mut edges = vec![(x, y, self.dependence(x, y))];
+        if x != y {
+            let path = union.shortest_path(&y, &x)?;
+            for pair in path.windows(2) {
+                let [&a, &b] = [pair[0], pair[1]];
+                edges.push((a, b, self.dependence(a, b)));
+            }
+        }
+        Some(NegativeCycle { edges })
+    }
+
+    /// How `y` depends on `x`, preferring an aggregate, then negation, where it depends in
+    /// several ways.
+    fn dependence(&self, x: &'a DomainId, y: &'a DomainId) -> Dependence {
+        if self.aggregated.contains_edge(&[x, y]) {
+            Dependence::Aggregate
+        } else if self.negative.contains_edge(&[x, y]) {
+            Dependence::Negative
+        } else {
+            Dependence::Positive
+        }
+    }
+
+    /// All the edges, regardless of sign.
+    fn union(&self) -> Digraph<&'a DomainId> {
+        let mut union = Digraph::default();
+        for &did in self.positive.verts().iter() {
+            union.insert_vert(did);
+        }
+        for [&x, &y] in self.positive.edges().chain(self.negative.edges()) {
+            union.insert_edge([x, y]);
+        }
+        union
+    }
+}
+
+impl AnnotatedRule {
+    /// The domains of the atoms this rule infers: those of its consequents, and those of their
+    /// subatoms if `subconsequence`.
+    pub fn consequent_domains(&self, subconsequence: bool) -> Vec<&DomainId> {
+        let mut dids = vec![];
+        for consequent in self.rule.consequents.iter() {
+            consequent.inferred_domains(&self.v2d, subconsequence, &mut dids);
+        }
+        dids
+    }
+}
+
+impl RuleAtom {
+    /// Adds the domains of the atoms inferred by this as a consequent: its own, and those of its
+    /// subatoms if `subconsequence`.
+    fn inferred_domains<'a>(
+        &'a self,
+        v2d: &'a VariableTypes,
+        subconsequence: bool,
+        dids: &mut Vec<&'a DomainId>,
+    ) {
+        dids.extend(self.domain_id(v2d));
+        if let (true, RuleAtom::Construct { args, .. }) = (subconsequence, self) {
+            for arg in args {
+                arg.inferred_domains(v2d, subconsequence, dids);
+            }
+        }
     }
 }
 
 impl ExecutableProgram {
-    pub fn get_used_undeclared(&self) -> &HashSet<DomainId> {
+    pub fn domain_dependency_graph(&self) -> DomainDependencyGraph<'_> {
+        let mut positive = Digraph::default();
+        let mut negative = Digraph::default();
+        let mut aggregated = Digraph::default();
+        for annotated_rule in self.annotated_rules.iter() {
+            let AnnotatedRule { rule, v2d, .. } = annotated_rule;
+            let consequent_dids =
+                annotated_rule.consequent_domains(self.executable_config.subconsequence);
+            for &did in consequent_dids.iter() {
+                positive.insert_vert(did);
+            }
+            // the literals of aggregates are like negative antecedents
+            let aggregated_ras = rule.aggregates().into_iter().flat_map(|aggregate| {
+                aggregate.literals.iter().map(|literal| (Dependence::Aggregate, &literal.ra))
+            });
+            let antecedents = rule.antecedents.iter().map(|literal| {
+                let dependence = match literal.sign {
+                    Sign::Pos => Dependence::Positive,
+                    Sign::Neg => Dependence::Negative,
+                };
+                (dependence, &literal.ra)
+            });
+            for (dependence, ra) in antecedents.chain(aggregated_ras) {
+                let Some(antecedent_did) = ra.domain_id(v2d) else { continue };
+                positive.insert_vert(antecedent_did);
+                for &did in consequent_dids.iter() {
+                    let edge = [antecedent_did, did];
+                    match dependence {
+                        Dependence::Positive => positive.insert_edge(edge),
+                        Dependence::Negative => negative.insert_edge(edge),
+                        Dependence::Aggregate => {
+                            negative.insert_edge(edge);
+                            aggregated.insert_edge(edge);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut ddg = DomainDependencyGraph { positive, negative, aggregated, sccs: vec![] };
+        let union = ddg.union();
+        for scc in union.sccs() {
+            let domains: Vec<&DomainId> = scc.into_iter().copied().collect();
+            let negative = domains
+                .iter()
+                .any(|x| ddg.negative.successors(x).any(|y| domains.binary_search(y).is_ok()));
+            ddg.sccs.push(DependencyScc { domains, negative });
+        }
+        ddg
+    }
+    pub fn get_used_undeclared(&self) -> &HashMap<DomainId, Span> {
         &self.used_undeclared
     }
+    /// The program's queries, in order, each with the types of its variables.
+    pub fn queries(&self) -> &[AnnotatedRule] {
+        &self.annotated_queries
+    }
     pub fn is_sealed(&self, did: &DomainId) -> bool {
         self.sealers_modifiers.get(did).map(|dsm| !dsm.sealers.is_empty()).unwrap_or(false)
     }
-    pub fn unbounded_domain_cycle(&self) -> Option<&DomainId> {
+    pub fn unbounded_domain_cycle(&self) -> Option<UnboundedDomainCycle<'_>> {
         // pass 3: (termination detection) build argument graph, throw error on cycle
         let mut ag = ArgumentGraph::default();
-        for AnnotatedRule { rule, v2d } in self.annotated_rules.iter() {
-            populate_argument_graph(&mut ag, rule, v2d);
+        let mut edge_rules = ArgumentEdgeRules::default();
+        for AnnotatedRule { rule, v2d, .. } in self.annotated_rules.iter() {
+            populate_argument_graph(&mut ag, &mut edge_rules, rule, v2d);
         }
-        ag.transitively_close();
-        for &did in ag.verts().iter() {
-            if ag.contains_edge(&[did, did]) {
-                return Some(did);
+        let cycle = ag.find_cycle()?;
+        let edges = cycle.windows(2).map(|pair| {
+            let [&outer, &inner] = [pair[0], pair[1]];
+            let (rule, span) = edge_rules[&[outer, inner]];
+            ArgumentEdge { outer, inner, rule, span }
+        });
+        Some(UnboundedDomainCycle { edges: edges.collect() })
+    }
+    /// Each rule inferring values it computes (see `Rule::computed_variables`) in a domain in the
+    /// same strongly connected component as one of its positive antecedents. Termination is
+    /// uncertain, as such rules may compute ever new values from their own consequents.
+    pub fn unbounded_values(&self) -> Vec<UnboundedValues<'_>> {
+        let ddg = self.domain_dependency_graph();
+        let scc_of: HashMap<&DomainId, usize> = ddg
+            .sccs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, scc)| scc.domains.iter().map(move |&did| (did, i)))
+            .collect();
+        let mut unbounded = vec![];
+        for AnnotatedRule { rule, v2d, .. } in self.annotated_rules.iter() {
+            let computed = rule.computed_variables();
+            let found = rule.consequents.iter().find_map(|consequent| {
+                let mut vids = HashSet::default();
+                consequent.variables(&mut vids);
+                let (vid, condition) = computed.iter().find(|(vid, _)| vids.contains(vid))?;
+                let mut dids = vec![];
+                consequent.inferred_domains(v2d, self.executable_config.subconsequence, &mut dids);
+                let antecedent = rule.antecedents.iter().find(|antecedent| {
+                    let did = antecedent.ra.domain_id(v2d).expect("typed");
+                    antecedent.sign == Sign::Pos
+                        && dids.iter().any(|consequent_did| scc_of[consequent_did] == scc_of[did])
+                })?;
+                Some(UnboundedValues {
+                    rule,
+                    condition,
+                    vid: vid.clone(),
+                    consequent,
+                    antecedent: &antecedent.ra,
+                })
+            });
+            unbounded.extend(found);
+        }
+        unbounded
+    }
+    /// Aggregates are evaluated over complete domains, so programs with aggregates must be
+    /// stratified. Otherwise, returns an aggregate, preferring one with a cycle through it.
+    pub fn unstratified_aggregate(&self) -> Option<UnstratifiedAggregate<'_>> {
+        let first =
+            self.annotated_rules.iter().find_map(|ar| ar.rule.aggregates().first().copied())?;
+        let ddg = self.domain_dependency_graph();
+        let cycle = ddg.negative_cycle()?;
+        let union = ddg.union();
+        let subconsequence = self.executable_config.subconsequence;
+        for annotated_rule in self.annotated_rules.iter() {
+            let consequent_dids = annotated_rule.consequent_domains(subconsequence);
+            for aggregate in annotated_rule.rule.aggregates() {
+                for literal in aggregate.literals.iter() {
+                    let Some(did) = literal.ra.domain_id(&annotated_rule.v2d) else { continue };
+                    for &consequent_did in consequent_dids.iter() {
+                        if let Some(cycle) = ddg.cycle_through(&union, did, consequent_did) {
+                            return Some(UnstratifiedAggregate {
+                                aggregate,
+                                did: Some(did),
+                                cycle,
+                            });
+                        }
+                    }
+                }
             }
         }
-        None
+        Some(UnstratifiedAggregate { aggregate: first, did: None, cycle })
     }
 }
 
@@ -306,21 +770,101 @@ impl Rule {
         set
     }
     pub fn is_enumerable_variable(&self, vid: &VariableId) -> bool {
-        let mut found = false;
-        let visitor = &mut |ra: &RuleAtom| {
-            if let RuleAtom::Variable { vid: vid2, .. } = ra {
-                if vid == vid2 {
-                    found = true;
+        self.bound_variables().contains(vid)
+    }
+    /// The variables bound once the positive antecedents are matched, and the conditions are
+    /// evaluated, each as soon as it can be. Includes `aggregate_bound_variables`.
+    pub fn bound_variables(&self) -> HashSet<VariableId> {
+        let mut bound = self.aggregate_bound_variables();
+        for antecedent in &self.antecedents {
+            if antecedent.sign == Sign::Pos {
+                antecedent.ra.variables(&mut bound);
+            }
+        }
+        self.evaluation_order(&mut bound);
+        bound
+    }
+    /// The variables local to aggregates (i.e., occurring only within them) that are bound by
+    /// their positive literals. Aggregates bind these themselves, so they may be taken as bound
+    /// throughout the rule, as they occur nowhere else.
+    pub(crate) fn aggregate_bound_variables(&self) -> HashSet<VariableId> {
+        let mut outer = HashSet::default();
+        for ra in self.consequents.iter().chain(self.antecedents.iter().map(|lit| &lit.ra)) {
+            ra.variables(&mut outer);
+        }
+        for expr in self.conditions.iter().flat_map(Condition::exprs) {
+            expr.visit(&mut |expr| {
+                if let Expr::Atom(ra) = expr {
+                    ra.variables(&mut outer)
                 }
+            });
+        }
+        let mut bound = HashSet::default();
+        for aggregate in self.aggregates() {
+            for literal in aggregate.literals.iter().filter(|literal| literal.sign == Sign::Pos) {
+                literal.ra.variables(&mut bound);
             }
-        };
-
+        }
+        bound.retain(|vid| !outer.contains(vid));
+        bound
+    }
+    /// The aggregates of the conditions, in order.
+    pub fn aggregates(&self) -> Vec<&Aggregate> {
+        let mut aggregates = vec![];
+        for expr in self.conditions.iter().flat_map(Condition::exprs) {
+            expr.visit(&mut |expr| {
+                if let Expr::Aggregate(aggregate) = expr {
+                    aggregates.push(aggregate)
+                }
+            });
+        }
+        aggregates
+    }
+    /// The variables whose values are computed by conditions (see `Condition::computes`), or
+    /// copied from those, each with the condition binding it, in evaluation order.
+    pub fn computed_variables(&self) -> Vec<(VariableId, &Condition)> {
+        let mut bound = self.aggregate_bound_variables();
         for antecedent in &self.antecedents {
             if antecedent.sign == Sign::Pos {
-                antecedent.ra.visit_subatoms(visitor);
+                antecedent.ra.variables(&mut bound);
+            }
+        }
+        let mut computed = Vec::<(VariableId, &Condition)>::default();
+        for condition in self.evaluation_order(&mut bound.clone()) {
+            let mut vids = HashSet::default();
+            for expr in condition.exprs() {
+                expr.variables(&mut vids);
+            }
+            let copied = computed.iter().any(|(vid, _)| vids.contains(vid));
+            let before = bound.clone();
+            condition.bind(&mut bound);
+            for vid in bound.difference(&before) {
+                if copied || condition.computes(vid) {
+                    computed.push((vid.clone(), condition));
+                }
+            }
+        }
+        computed
+    }
+    /// Orders the conditions such that each can be evaluated once those before it are, given that
+    /// the variables in `bound` are bound. Extends `bound` with the variables the conditions bind.
+    /// Conditions that can never be evaluated are omitted.
+    pub(crate) fn evaluation_order(&self, bound: &mut HashSet<VariableId>) -> Vec<&Condition> {
+        let mut pending: Vec<&Condition> = self.conditions.iter().collect();
+        let mut order = vec![];
+        loop {
+            let evaluated = order.len();
+            pending.retain(|&condition| {
+                let evaluable = condition.bind(bound);
+                if evaluable {
+                    order.push(condition);
+                }
+                !evaluable
+            });
+            if order.len() == evaluated {
+                return order;
             }
         }
-        found
     }
     fn contains_pos_antecedent(&self, ra: &RuleAtom, subconsequence: bool) -> bool {
         let mut pos_antecedent_ras =
@@ -343,49 +887,73 @@ impl Rule {
         self.consequents.iter().map(|consequent| Self {
             consequents: vec![consequent.clone()],
             antecedents: self.antecedents.clone(),
+            conditions: self.conditions.clone(),
+            span: self.span,
         })
     }
+    /// The span of the first occurrence of the given variable, or the rule's span if it does not occur.
+    pub fn variable_span(&self, vid: &VariableId) -> Span {
+        let mut found = None;
+        for ra in self.root_atoms() {
+            ra.visit_subatoms(&mut |ra| match ra {
+                RuleAtom::Variable { vid: vid2, span, .. } if vid == vid2 && found.is_none() => {
+                    found = Some(*span)
+                }
+                _ => {}
+            });
+        }
+        found.unwrap_or(self.span)
+    }
     pub fn root_vars(&self) -> impl Iterator<Item = &VariableId> {
         self.root_atoms().filter_map(|ra| match ra {
             RuleAtom::Variable { vid, .. } => Some(vid),
             _ => None,
         })
     }
-    fn used_dids(&self, dids: &mut HashSet<DomainId>) {
+    fn used_dids(&self, dids: &mut HashMap<DomainId, Span>) {
         for ra in self.root_atoms() {
             ra.used_dids(dids)
         }
     }
+    /// The consequents, the atoms of the antecedents, and the operands of the conditions.
     pub fn root_atoms(&self) -> impl Iterator<Item = &RuleAtom> {
-        self.consequents.iter().chain(self.antecedents.iter().map(|lit| &lit.ra))
+        let operands = self.conditions.iter().flat_map(Condition::operands);
+        self.consequents.iter().chain(self.antecedents.iter().map(|lit| &lit.ra)).chain(operands)
     }
     pub fn root_atoms_mut(&mut self) -> impl Iterator<Item = &mut RuleAtom> {
-        self.consequents.iter_mut().chain(self.antecedents.iter_mut().map(|lit| &mut lit.ra))
+        let operands = self.conditions.iter_mut().flat_map(Condition::operands_mut);
+        let antecedents = self.antecedents.iter_mut().map(|lit| &mut lit.ra);
+        self.consequents.iter_mut().chain(antecedents).chain(operands)
     }
     fn rule_type_variables(
         &self,
         dd: &DomainDefinitions,
     ) -> Result<VariableTypes, ExecutableRuleError> {
         let mut vt = VariableTypes::default();
+        let mut vt_spans = VariableTypeSpans::default();
         let mut vids = HashSet::<VariableId>::default();
         for ra in self.root_atoms() {
             ra.variables(&mut vids);
-            if let Err(err) = ra.type_variables(dd, &mut vt) {
-                return Err(err);
+            ra.type_variables(dd, &mut vt, &mut vt_spans)?;
+        }
+        // conditions type their sides alike, so types propagate through them
+        loop {
+            let typed = vt.len();
+            for condition in self.conditions.iter() {
+                condition.type_variables(&mut vt, &mut vt_spans)?;
+            }
+            if vt.len() == typed {
+                break;
             }
         }
         if let Some(vid) = vids.iter().find(|&vid| !vt.contains_key(vid)) {
-            Err(ExecutableRuleError::NoTypes(vid.clone()))
+            Err(ExecutableRuleError::NoTypes { vid: vid.clone(), span: self.variable_span(vid) })
         } else {
             // enumerability check
-            let mut enumerable = HashSet::default();
-            for antecedent in &self.antecedents {
-                if let RuleLiteral { sign: Sign::Pos, ra } = antecedent {
-                    ra.variables(&mut enumerable);
-                }
-            }
+            let enumerable = self.bound_variables();
             if let Some(vid) = vids.difference(&enumerable).next() {
-                Err(ExecutableRuleError::VariableNotEnumerable(vid.clone()))
+                let span = self.variable_span(vid);
+                Err(ExecutableRuleError::VariableNotEnumerable { vid: vid.clone(), span })
             } else {
                 Ok(vt)
             }
@@ -426,8 +994,316 @@ impl Constant {
     }
 }
 
+impl Condition {
+    /// Whether this can be evaluated once the variables in `bound` are bound, i.e., all its
+    /// variables are bound, or it binds a lone variable given the other arguments: a comparison
+    /// with `=` and an expression on the other side, or `split` given the string and separator.
+    /// In the latter case, the variable is added to `bound`.
+    pub(crate) fn bind(&self, bound: &mut HashSet<VariableId>) -> bool {
+        let binding = match self {
+            Self::Compare { left, op, right, .. } => {
+                match (op, left.is_bound_by(bound), right.is_bound_by(bound)) {
+                    (_, true, true) => return true,
+                    (Comparison::Eq, false, true) => left.as_variable(),
+                    (Comparison::Eq, true, false) => right.as_variable(),
+                    _ => None,
+                }
+            }
+            Self::Builtin { sign, predicate, args, .. } => {
+                if args.iter().all(|arg| arg.is_bound_by(bound)) {
+                    return true;
+                }
+                match (sign, predicate, args.as_slice()) {
+                    (Sign::Pos, Predicate::Split, [string, separator, piece])
+                        if string.is_bound_by(bound) && separator.is_bound_by(bound) =>
+                    {
+                        piece.as_variable()
+                    }
+                    _ => None,
+                }
+            }
+        };
+        binding.is_some_and(|vid| bound.insert(vid.clone()))
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Compare { span, .. } | Self::Builtin { span, .. } => *span,
+        }
+    }
+    /// Whether this computes the value of the variable it binds, e.g., `N = M + 1` or
+    /// `split(S, "/", P)`, rather than copying a value or aggregating a complete domain, e.g.,
+    /// `X = Y` or `N = count{ X : a(X) }`.
+    fn computes(&self, vid: &VariableId) -> bool {
+        match self {
+            Self::Compare { left, op: Comparison::Eq, right, .. } => {
+                [(left, right), (right, left)].into_iter().any(|(variable, expr)| {
+                    variable.as_variable() == Some(vid)
+                        && !matches!(expr, Expr::Atom(_) | Expr::Aggregate(_))
+                })
+            }
+            Self::Builtin { predicate: Predicate::Split, args, .. } => {
+                args.get(2).and_then(Expr::as_variable) == Some(vid)
+            }
+            _ => false,
+        }
+    }
+    fn exprs(&self) -> Vec<&Expr> {
+        match self {
+            Self::Compare { left, right, .. } => vec![left, right],
+            Self::Builtin { args, .. } => args.iter().collect(),
+        }
+    }
+    /// The variables and constants of the expressions of this.
+    pub fn operands(&self) -> Vec<&RuleAtom> {
+        let mut operands = vec![];
+        for expr in self.exprs() {
+            expr.visit_operands(&mut |ra| operands.push(ra));
+        }
+        operands
+    }
+    pub fn operands_mut(&mut self) -> Vec<&mut RuleAtom> {
+        let exprs: Vec<&mut Expr> = match self {
+            Self::Compare { left, right, .. } => vec![left, right],
+            Self::Builtin { args, .. } => args.iter_mut().collect(),
+        };
+        let mut operands = vec![];
+        for expr in exprs {
+            expr.collect_operands_mut(&mut operands);
+        }
+        operands
+    }
+    /// Types the variables of this, given the types of its expressions where they are known,
+    /// erring where types conflict. Comparisons are typed only once the type of a side is known.
+    fn type_variables(
+        &self,
+        vt: &mut VariableTypes,
+        vt_spans: &mut VariableTypeSpans,
+    ) -> Result<(), ExecutableRuleError> {
+        match self {
+            Self::Compare { left, op, right, span } => {
+                let Some(did) = left.domain_id(vt).or_else(|| right.domain_id(vt)).cloned() else {
+                    return Ok(());
+                };
+                if !did.is_primitive() && !op.is_equality() {
+                    return Err(ExecutableRuleError::UncomparableOperands {
+                        did,
+                        op: *op,
+                        span: *span,
+                    });
+                }
+                left.type_as(&did, vt, vt_spans)?;
+                right.type_as(&did, vt, vt_spans)
+            }
+            Self::Builtin { predicate, args, span, .. } => {
+                let name = predicate.name();
+                check_builtin_arity(name, Some(predicate.arity()), args.len(), *span)?;
+                for arg in args {
+                    arg.type_as(DomainId::str(), vt, vt_spans)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Errs unless a built-in of the given arity (`None` for one or more) was given `arg_count` arguments.
+fn check_builtin_arity(
+    name: &'static str,
+    arity: Option<usize>,
+    arg_count: usize,
+    span: Span,
+) -> Result<(), ExecutableRuleError> {
+    let ok = match arity {
+        Some(arity) => arg_count == arity,
+        None => arg_count > 0,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(ExecutableRuleError::WrongBuiltinArity { name, arity, arg_count, span })
+    }
+}
+
+impl Function {
+    /// The number of arguments, or `None` if it takes one or more.
+    pub fn arity(self) -> Option<usize> {
+        match self {
+            Self::Min | Self::Max | Self::Concat => None,
+            Self::Length | Self::Lowercase => Some(1),
+        }
+    }
+    /// The type of each argument.
+    pub fn param_did(self) -> &'static DomainId {
+        match self {
+            Self::Min | Self::Max => DomainId::int(),
+            Self::Concat | Self::Length | Self::Lowercase => DomainId::str(),
+        }
+    }
+    pub fn result_did(self) -> &'static DomainId {
+        match self {
+            Self::Min | Self::Max | Self::Length => DomainId::int(),
+            Self::Concat | Self::Lowercase => DomainId::str(),
+        }
+    }
+}
+
+impl Predicate {
+    /// The number of arguments, each of which is a string.
+    pub fn arity(self) -> usize {
+        match self {
+            Self::StartsWith | Self::EndsWith | Self::Contains => 2,
+            Self::Split => 3,
+        }
+    }
+}
+
+impl Expr {
+    /// The variable this consists of, if any.
+    pub fn as_variable(&self) -> Option<&VariableId> {
+        match self {
+            Self::Atom(RuleAtom::Variable { vid, .. }) => Some(vid),
+            _ => None,
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Atom(ra) => ra.span(),
+            Self::Arithmetic { args, .. } => args[0].span().to(args[1].span()),
+            Self::Call { span, .. } => *span,
+            Self::Aggregate(aggregate) => aggregate.span,
+        }
+    }
+    /// The type of the value of this, if it is known, given the types of variables.
+    fn domain_id<'a>(&'a self, vt: &'a VariableTypes) -> Option<&'a DomainId> {
+        match self {
+            Self::Atom(ra) => ra.domain_id(vt),
+            Self::Arithmetic { .. } => Some(DomainId::int()),
+            Self::Call { function, .. } => Some(function.result_did()),
+            Self::Aggregate(_) => Some(DomainId::int()),
+        }
+    }
+    /// Types the variables of this, given that its value has type `did`, erring where types
+    /// conflict.
+    fn type_as(
+        &self,
+        did: &DomainId,
+        vt: &mut VariableTypes,
+        vt_spans: &mut VariableTypeSpans,
+    ) -> Result<(), ExecutableRuleError> {
+        if let Self::Atom(RuleAtom::Variable { vid, span, .. }) = self {
+            return type_variable(vid, did, *span, vt, vt_spans);
+        }
+        let got = self.domain_id(vt).expect("not a variable");
+        if got != did {
+            return Err(ExecutableRuleError::MistypedOperand {
+                expected: did.clone(),
+                got: got.clone(),
+                span: self.span(),
+            });
+        }
+        match self {
+            Self::Atom(_) => Ok(()),
+            Self::Arithmetic { args, .. } => {
+                for arg in args.iter() {
+                    arg.type_as(DomainId::int(), vt, vt_spans)?;
+                }
+                Ok(())
+            }
+            Self::Call { function, args, span } => {
+                check_builtin_arity(function.name(), function.arity(), args.len(), *span)?;
+                for arg in args {
+                    arg.type_as(function.param_did(), vt, vt_spans)?;
+                }
+                Ok(())
+            }
+            Self::Aggregate(aggregate) => aggregate.type_variables(vt, vt_spans),
+        }
+    }
+    fn is_bound_by(&self, bound: &HashSet<VariableId>) -> bool {
+        let mut vids = HashSet::default();
+        self.variables(&mut vids);
+        vids.is_subset(bound)
+    }
+    pub(crate) fn variables(&self, vids: &mut HashSet<VariableId>) {
+        self.visit_operands(&mut |ra| ra.variables(vids))
+    }
+    /// Visits the operands of this, including the atoms of its aggregates.
+    fn visit_operands<'a>(&'a self, visitor: &mut impl FnMut(&'a RuleAtom)) {
+        self.visit(&mut |expr| match expr {
+            Self::Atom(ra) => visitor(ra),
+            Self::Aggregate(aggregate) => aggregate.atoms().for_each(&mut *visitor),
+            Self::Arithmetic { .. } | Self::Call { .. } => {}
+        })
+    }
+    /// Visits this, and its subexpressions, in pre-order.
+    fn visit<'a>(&'a self, visitor: &mut impl FnMut(&'a Expr)) {
+        visitor(self);
+        match self {
+            Self::Atom(_) | Self::Aggregate(_) => {}
+            Self::Arithmetic { args, .. } => {
+                for arg in args.iter() {
+                    arg.visit(visitor)
+                }
+            }
+            Self::Call { args, .. } => {
+                for arg in args {
+                    arg.visit(visitor)
+                }
+            }
+        }
+    }
+    fn collect_operands_mut<'a>(&'a mut self, operands: &mut Vec<&'a mut RuleAtom>) {
+        match self {
+            Self::Atom(ra) => operands.push(ra),
+            Self::Arithmetic { args, .. } => {
+                for arg in args.iter_mut() {
+                    arg.collect_operands_mut(operands)
+                }
+            }
+            Self::Call { args, .. } => {
+                for arg in args {
+                    arg.collect_operands_mut(operands)
+                }
+            }
+            Self::Aggregate(Aggregate { terms, literals, .. }) => {
+                operands.extend(terms.iter_mut().chain(literals.iter_mut().map(|lit| &mut lit.ra)))
+            }
+        }
+    }
+}
+
+impl Aggregate {
+    /// The terms, and the atoms of the literals.
+    pub fn atoms(&self) -> impl Iterator<Item = &RuleAtom> {
+        self.terms.iter().chain(self.literals.iter().map(|literal| &literal.ra))
+    }
+    /// Types the first term as an integer, unless counting. The atoms of this are otherwise typed
+    /// as root atoms of the rule.
+    fn type_variables(
+        &self,
+        vt: &mut VariableTypes,
+        vt_spans: &mut VariableTypeSpans,
+    ) -> Result<(), ExecutableRuleError> {
+        let int = DomainId::int();
+        match (self.aggregation, &self.terms[0]) {
+            (Aggregation::Count, _) => Ok(()),
+            (_, RuleAtom::Variable { vid, span, .. }) => {
+                type_variable(vid, int, *span, vt, vt_spans)
+            }
+            (_, term) => match term.apparent_did() {
+                Some(did) if did != int => Err(ExecutableRuleError::MistypedOperand {
+                    expected: int.clone(),
+                    got: did.clone(),
+                    span: term.span(),
+                }),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
 impl RuleAtom {
-    fn visit_subatoms(&self, visitor: &mut impl FnMut(&Self)) {
+    pub(crate) fn visit_subatoms(&self, visitor: &mut impl FnMut(&Self)) {
         visitor(self);
         if let Self::Construct { args, .. } = self {
             for arg in args {
@@ -435,7 +1311,7 @@ impl RuleAtom {
             }
         }
     }
-    fn visit_subatoms_mut(&mut self, visitor: &mut impl FnMut(&mut Self)) {
+    pub(crate) fn visit_subatoms_mut(&mut self, visitor: &mut impl FnMut(&mut Self)) {
         visitor(self);
         if let Self::Construct { args, .. } = self {
             for arg in args {
@@ -443,17 +1319,17 @@ impl RuleAtom {
             }
         }
     }
-    fn used_dids(&self, dids: &mut HashSet<DomainId>) {
+    fn used_dids(&self, dids: &mut HashMap<DomainId, Span>) {
         match self {
-            RuleAtom::Constant(_) => {}
-            RuleAtom::Variable { ascription, .. } => {
+            RuleAtom::Constant { .. } => {}
+            RuleAtom::Variable { ascription, span, .. } => {
                 if let Some(did) = ascription {
-                    dids.insert(did.clone());
+                    dids.entry(did.clone()).or_insert(*span);
                 }
             }
-            RuleAtom::Construct { did, args } => {
+            RuleAtom::Construct { did, args, span } => {
                 if !args.is_empty() {
-                    dids.insert(did.clone());
+                    dids.entry(did.clone()).or_insert(*span);
                     for arg in args {
                         arg.used_dids(dids)
                     }
@@ -464,7 +1340,7 @@ impl RuleAtom {
     pub fn apparent_did(&self) -> Option<&DomainId> {
         match self {
             RuleAtom::Construct { did, .. } => Some(did),
-            RuleAtom::Constant(c) => Some(c.domain_id()),
+            RuleAtom::Constant { c, .. } => Some(c.domain_id()),
             RuleAtom::Variable { .. } => None,
         }
     }
@@ -472,48 +1348,35 @@ impl RuleAtom {
         &self,
         dd: &DomainDefinitions,
         vt: &mut VariableTypes,
+        vt_spans: &mut VariableTypeSpans,
     ) -> Result<(), ExecutableRuleError> {
         match self {
-            Self::Constant(..) => {}
-            Self::Variable { vid, ascription } => {
+            Self::Constant { .. } => {}
+            Self::Variable { vid, ascription, span } => {
                 if let Some(did) = ascription {
-                    match vt.insert(vid.clone(), did.clone()) {
-                        Some(did2) if did != &did2 => {
-                            return Err(ExecutableRuleError::OneVariableTwoTypes {
-                                vid: vid.clone(),
-                                domains: [did.clone(), did2.clone()],
-                            });
-                        }
-                        _ => {}
-                    }
+                    type_variable(vid, did, *span, vt, vt_spans)?
                 }
             }
-            Self::Construct { did, args } => {
+            Self::Construct { did, args, span } => {
                 if let Some(param_dids) = dd.get(did) {
                     if param_dids.len() != args.len() {
                         return Err(ExecutableRuleError::WrongArity {
                             did: did.clone(),
                             param_count: param_dids.len(),
                             arg_count: args.len(),
+                            span: *span,
                         });
                     }
                     for (arg, param_did) in args.iter().zip(param_dids.iter()) {
-                        if let Self::Variable { vid, .. } = arg {
-                            match vt.insert(vid.clone(), param_did.clone()) {
-                                Some(param_did2) if param_did != &param_did2 => {
-                                    return Err(ExecutableRuleError::OneVariableTwoTypes {
-                                        vid: vid.clone(),
-                                        domains: [param_did.clone(), param_did2.clone()],
-                                    });
-                                }
-                                _ => {}
-                            }
+                        if let Self::Variable { vid, span, .. } = arg {
+                            type_variable(vid, param_did, *span, vt, vt_spans)?
                         } else if let Some(arg_did) = arg.apparent_did() {
                             if arg_did != param_did {
                                 return Err(ExecutableRuleError::MistypedArgument {
                                     constructor: did.clone(),
                                     expected: param_did.clone(),
                                     got: arg_did.clone(),
+                                    span: arg.span(),
                                 });
                             }
                         }
@@ -521,13 +1384,20 @@ impl RuleAtom {
                 }
 
                 for arg in args {
-                    arg.type_variables(dd, vt)?
+                    arg.type_variables(dd, vt, vt_spans)?
                 }
             }
         }
         Ok(())
     }
-    fn variables(&self, vids: &mut HashSet<VariableId>) {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Variable { span, .. }
+            | Self::Constant { span, .. }
+            | Self::Construct { span, .. } => *span,
+        }
+    }
+    pub(crate) fn variables(&self, vids: &mut HashSet<VariableId>) {
         match self {
             RuleAtom::Constant { .. } => {}
             RuleAtom::Variable { vid, .. } => drop(vids.insert(vid.clone())),
@@ -538,13 +1408,13 @@ impl RuleAtom {
             }
         }
     }
-    pub fn domain_id<'a>(&'a self, vt: &'a VariableTypes) -> Result<&'a DomainId, ()> {
+    pub fn domain_id<'a>(&'a self, vt: &'a VariableTypes) -> Option<&'a DomainId> {
         match self {
-            RuleAtom::Construct { did, .. } => Ok(did),
-            RuleAtom::Variable { vid, ascription } => {
-                ascription.as_ref().or_else(|| vt.get(vid)).ok_or(())
+            RuleAtom::Construct { did, .. } => Some(did),
+            RuleAtom::Variable { vid, ascription, .. } => {
+                ascription.as_ref().or_else(|| vt.get(vid))
             }
-            RuleAtom::Constant(c) => Ok(c.domain_id()),
+            RuleAtom::Constant { c, .. } => Some(c.domain_id()),
         }
     }
 }
@@ -552,16 +1422,40 @@ impl RuleAtom {
 impl std::fmt::Debug for Rule {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         util::CommaSep { iter: self.consequents.iter(), spaced: true }.fmt(f)?;
-        if !self.antecedents.is_empty() {
+        if self.has_body() {
             write!(f, " :- ")?;
         }
-        util::CommaSep { iter: self.antecedents.iter(), spaced: true }.fmt(f)
+        self.fmt_body(f)
+    }
+}
+
+impl std::fmt::Display for NegativeCycle<'_> {
+    /// Each edge as a rule-like dependency, e.g., `` `b :- !a`, `a :- b` ``, or as an aggregate,
+    /// e.g., `` `c` aggregates over `b` ``.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, (antecedent, consequent, dependence)) in self.edges.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match dependence {
+                Dependence::Positive => write!(f, "`{:?} :- {:?}`", consequent, antecedent)?,
+                Dependence::Negative => write!(f, "`{:?} :- !{:?}`", consequent, antecedent)?,
+                Dependence::Aggregate => {
+                    write!(f, "`{:?}` aggregates over `{:?}`", consequent, antecedent)?
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 impl std::fmt::Debug for SealBreak<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?} broke seal on {:?} in {:?}", self.modifier, self.did, self.sealer)
+        write!(
+            f,
+            "{:?} at {:?} broke seal on {:?} in {:?} at {:?}",
+            self.modifier, self.modifier_span, self.did, self.sealer, self.sealer_span
+        )
     }
 }
 
@@ -1,14 +1,17 @@
 use crate::*;
 use core::{
-    fmt::{Debug, Formatter, Result as FmtResult},
-    hash::Hash,
+    cmp::Ordering,
+    fmt::{Debug, Formatter, Result as FmtResult, Write as _},
+    hash::{Hash, Hasher},
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 
+/// A directed graph, stored as adjacency lists.
 #[derive(Debug)]
 pub struct Digraph<V: Ord> {
     verts: VecSet<V>,
-    edges: VecSet<[V; 2]>,
+    /// The successors of each vertex with any.
+    succs: BTreeMap<V, VecSet<V>>,
 }
 
 /// Structure used in debug printing. Prints elements separated by commas.
@@ -17,6 +20,10 @@ pub(crate) struct CommaSep<'a, T: Debug + 'a, I: IntoIterator<Item = &'a T> + Cl
     pub spaced: bool,
 }
 
+/// Structure used in debug printing. Prints a string as a literal, quoted and escaped such that
+/// `parse::string` reads it back.
+pub(crate) struct StringLiteral<'a>(pub &'a str);
+
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct VecSet<T: Ord> {
     elements: Vec<T>,
@@ -31,52 +38,187 @@ pub struct VecSetMutGuard<'a, T: Ord> {
 
 impl<T: Ord> Default for Digraph<T> {
     fn default() -> Self {
-        Digraph { edges: Default::default(), verts: Default::default() }
+        Digraph { succs: Default::default(), verts: Default::default() }
     }
 }
 
 impl<T: Ord> Digraph<T> {
     pub fn insert_edge(&mut self, edge: [T; 2])
     where
-        T: Clone + Hash + Eq,
+        T: Clone,
     {
         for vert in edge.iter() {
             self.insert_vert(vert.clone());
         }
-        self.edges.insert(edge);
+        let [x, y] = edge;
+        self.succs.entry(x).or_default().insert(y);
     }
     pub fn insert_vert(&mut self, vert: T)
     where
-        T: Clone + Hash + Eq,
+        T: Clone,
     {
-        self.verts.insert(vert.clone());
+        self.verts.insert(vert);
     }
-
-    pub fn contains_edge(&self, edge: &[T; 2]) -> bool
-    where
-        T: Hash + Eq,
-    {
-        self.edges.contains(edge)
+    pub fn contains_edge(&self, edge: &[T; 2]) -> bool {
+        let [x, y] = edge;
+        self.succs.get(x).map(|succs| succs.contains(y)).unwrap_or(false)
     }
-    pub fn transitively_close(&mut self)
-    where
-        T: Eq + Hash + Clone,
-    {
-        for x in self.verts.iter() {
-            for y in self.verts.iter() {
-                for z in self.verts.iter() {
-                    if self.edges.contains(&[x.clone(), y.clone()])
-                        && self.edges.contains(&[y.clone(), z.clone()])
-                        && !self.edges.contains(&[x.clone(), z.clone()])
-                    {
-                        self.edges.insert([x.clone(), z.clone()]);
+    pub fn verts(&self) -> &VecSet<T> {
+        &self.verts
+    }
+    /// In order.
+    pub fn successors<'a>(&'a self, vert: &T) -> impl Iterator<Item = &'a T> + 'a {
+        self.succs.get(vert).into_iter().flat_map(VecSet::iter)
+    }
+    /// In order.
+    pub fn edges(&self) -> impl Iterator<Item = [&T; 2]> {
+        self.succs.iter().flat_map(|(x, succs)| succs.iter().map(move |y| [x, y]))
+    }
+    /// Whether some path of at least one edge leads from `from` to `to`.
+    pub fn reaches(&self, from: &T, to: &T) -> bool {
+        self.shortest_path(from, to).is_some()
+    }
+    /// The vertices to which some path of at least one edge leads from `from`.
+    pub fn reachable<'a>(&'a self, from: &T) -> BTreeSet<&'a T> {
+        let mut reached = BTreeSet::default();
+        let mut stack: Vec<&T> = self.successors(from).collect();
+        while let Some(x) = stack.pop() {
+            if reached.insert(x) {
+                stack.extend(self.successors(x));
+            }
+        }
+        reached
+    }
+    /// The vertices along a shortest path of at least one edge, from `from` to `to` (inclusive).
+    /// If `from` is `to`, this is a cycle, which starts and ends with it.
+    pub fn shortest_path<'a>(&'a self, from: &'a T, to: &T) -> Option<Vec<&'a T>> {
+        // breadth-first search, remembering the predecessor of each vertex
+        let mut pred = BTreeMap::<&T, &T>::default();
+        let mut queue = VecDeque::from([from]);
+        while let Some(x) = queue.pop_front() {
+            for y in self.successors(x) {
+                if pred.contains_key(y) {
+                    continue;
+                }
+                pred.insert(y, x);
+                if y == to {
+                    let mut path = vec![y];
+                    while path.len() == 1 || path.last() != Some(&from) {
+                        path.push(pred[path.last().expect("nonempty")]);
                     }
+                    path.reverse();
+                    return Some(path);
                 }
+                queue.push_back(y);
             }
         }
+        None
     }
-    pub fn verts(&self) -> &VecSet<T> {
-        &self.verts
+    /// The strongly connected components (each sorted), in topological order, i.e., each
+    /// precedes all those it reaches. Computed with Tarjan's algorithm.
+    pub fn sccs(&self) -> Vec<Vec<&T>> {
+        let mut tarjan = Tarjan {
+            digraph: self,
+            indexes: Default::default(),
+            lowlinks: Default::default(),
+            stack: vec![],
+            on_stack: Default::default(),
+            sccs: vec![],
+        };
+        for vert in self.verts.iter() {
+            if !tarjan.indexes.contains_key(vert) {
+                tarjan.visit(vert);
+            }
+        }
+        // Tarjan's algorithm finds each component after all those it reaches
+        tarjan.sccs.reverse();
+        tarjan.sccs
+    }
+    /// Whether the given strongly connected component has a cycle, i.e., it has several vertices,
+    /// or its vertex has an edge to itself.
+    pub fn is_cyclic_scc(&self, scc: &[&T]) -> bool {
+        match scc {
+            [vert] => self.successors(vert).any(|succ| succ == *vert),
+            _ => scc.len() > 1,
+        }
+    }
+    /// All vertices, each preceding all those it reaches. Fails with a cycle (as returned by
+    /// `find_cycle`) if there is none.
+    pub fn topological_order(&self) -> Result<Vec<&T>, Vec<&T>> {
+        match self.find_cycle() {
+            Some(cycle) => Err(cycle),
+            None => Ok(self.sccs().into_iter().flatten().collect()),
+        }
+    }
+    /// A shortest cycle through the least vertex on any cycle, as returned by `shortest_path`.
+    pub fn find_cycle(&self) -> Option<Vec<&T>> {
+        let vert = self
+            .sccs()
+            .into_iter()
+            .filter(|scc| self.is_cyclic_scc(scc))
+            .map(|scc| scc[0])
+            .min()?;
+        self.shortest_path(vert, vert)
+    }
+}
+
+/// State of Tarjan's algorithm, finding the strongly connected components of `digraph`.
+struct Tarjan<'a, T: Ord> {
+    digraph: &'a Digraph<T>,
+    /// Orders vertices by when they were first visited.
+    indexes: BTreeMap<&'a T, usize>,
+    /// The least index of a vertex known to be reachable from each vertex, and on the stack.
+    lowlinks: BTreeMap<&'a T, usize>,
+    stack: Vec<&'a T>,
+    on_stack: BTreeSet<&'a T>,
+    sccs: Vec<Vec<&'a T>>,
+}
+
+impl<'a, T: Ord> Tarjan<'a, T> {
+    /// Visits `root`, and then each unvisited vertex it reaches, depth-first.
+    /// The vertices being visited are kept on an explicit stack, each with its successors yet to be
+    /// visited, such that long paths cannot overflow the call stack.
+    fn visit(&mut self, root: &'a T) {
+        self.discover(root);
+        let mut visiting = vec![(root, self.digraph.successors(root))];
+        while let Some((x, succs)) = visiting.last_mut() {
+            let x = *x;
+            if let Some(y) = succs.next() {
+                if !self.indexes.contains_key(y) {
+                    self.discover(y);
+                    visiting.push((y, self.digraph.successors(y)));
+                } else if self.on_stack.contains(y) {
+                    self.lower_lowlink(x, self.indexes[y]);
+                }
+                continue;
+            }
+            visiting.pop();
+            if let Some(&(parent, _)) = visiting.last() {
+                self.lower_lowlink(parent, self.lowlinks[x]);
+            }
+            if self.lowlinks[x] == self.indexes[x] {
+                // x is the root of a component, comprising it and all above it on the stack
+                let mut scc = vec![];
+                while scc.last() != Some(&x) {
+                    let y = self.stack.pop().expect("x is on the stack");
+                    self.on_stack.remove(y);
+                    scc.push(y);
+                }
+                scc.sort();
+                self.sccs.push(scc);
+            }
+        }
+    }
+    fn discover(&mut self, x: &'a T) {
+        let index = self.indexes.len();
+        self.indexes.insert(x, index);
+        self.lowlinks.insert(x, index);
+        self.stack.push(x);
+        self.on_stack.insert(x);
+    }
+    fn lower_lowlink(&mut self, x: &'a T, lowlink: usize) {
+        let x_lowlink = self.lowlinks.get_mut(x).expect("visited");
+        *x_lowlink = (*x_lowlink).min(lowlink);
     }
 }
 
@@ -105,6 +247,46 @@ pub fn collect_map_lossless<K: Copy + Eq + Hash, V, I: IntoIterator<Item = (K, V
     Ok(map)
 }
 
+/// Byte offsets at which the lines of `source` start.
+pub fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0).chain(source.match_indices('\n').map(|(idx, _)| idx + 1)).collect()
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Self) -> Self {
+        Self { end: other.end, ..self }
+    }
+    /// Sets `line` and `col` according to `start`, given the `source` and its `line_starts`.
+    pub fn locate(&mut self, source: &str, line_starts: &[usize]) {
+        let start = self.start as usize;
+        let line_idx = line_starts.partition_point(|&line_start| line_start <= start) - 1;
+        let line_start = line_starts[line_idx];
+        self.line = line_idx as u32 + 1;
+        self.col = source[line_start..start].chars().count() as u32 + 1;
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+impl Eq for Span {}
+impl PartialOrd for Span {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Span {
+    fn cmp(&self, _: &Self) -> Ordering {
+        Ordering::Equal
+    }
+}
+impl Hash for Span {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
 impl<'a, T: Ord> Drop for VecSetMutGuard<'a, T> {
     fn drop(&mut self) {
         self.set.elements.sort();
@@ -151,9 +333,9 @@ impl<T: Ord> VecSet<T> {
         }
     }
     pub fn contains(&self, t: &T) -> bool {
-        self.elements.binary_search(&t).is_ok()
+        self.elements.binary_search(t).is_ok()
     }
-    pub fn as_vec_mut(&mut self) -> VecSetMutGuard<T> {
+    pub fn as_vec_mut(&mut self) -> VecSetMutGuard<'_, T> {
         VecSetMutGuard { set: self }
     }
     pub fn iter(&self) -> impl Iterator<Item = &T> {
@@ -193,9 +375,15 @@ impl<'a, T: Debug + 'a, I: IntoIterator<Item = &'a T> + Clone> Debug for CommaSe
 impl Debug for RuleAtom {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
-            Self::Variable { vid, .. } => vid.fmt(f),
-            Self::Constant(c) => c.fmt(f),
-            Self::Construct { did, args } => {
+            Self::Variable { vid, ascription, .. } => {
+                vid.fmt(f)?;
+                if let Some(did) = ascription {
+                    write!(f, ":{:?}", did)?
+                }
+                Ok(())
+            }
+            Self::Constant { c, .. } => c.fmt(f),
+            Self::Construct { did, args, .. } => {
                 did.fmt(f)?;
                 if !args.is_empty() {
                     write!(f, "({:?})", CommaSep { iter: args, spaced: false })?
@@ -215,9 +403,14 @@ impl Debug for RuleLiteral {
 }
 
 impl Debug for Statement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        self.kind.fmt(f)
+    }
+}
+impl Debug for StatementKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
-            Statement::Decl(vec) => {
+            StatementKind::Decl(vec) => {
                 write!(f, "decl ")?;
                 for (i, did) in vec.iter().enumerate() {
                     if i > 0 {
@@ -227,26 +420,172 @@ impl Debug for Statement {
                 }
                 Ok(())
             }
-            Statement::Rule(Rule { consequents, antecedents }) => {
-                write!(f, "rule {:?}", CommaSep { iter: consequents, spaced: true })?;
-                if !antecedents.is_empty() {
-                    write!(f, " :- {:?}", CommaSep { iter: antecedents, spaced: true })?;
+            StatementKind::Rule(rule) => write!(f, "rule {:?}", rule),
+            StatementKind::Query(rule) => {
+                write!(f, "query ")?;
+                rule.fmt_body(f)
+            }
+            StatementKind::Emit(did) => write!(f, "emit {:?}", did),
+            StatementKind::Seal(did) => write!(f, "seal {:?}", did),
+            StatementKind::Defn { did, params } => {
+                write!(f, "defn {:?}", did)?;
+                if !params.is_empty() {
+                    write!(f, "({:?})", CommaSep { iter: params, spaced: false })?
                 }
                 Ok(())
             }
-            Statement::Emit(did) => write!(f, "emit {:?}", did),
-            Statement::Seal(did) => write!(f, "seal {:?}", did),
-            Statement::Defn { did, params } => {
-                write!(f, "defn {:?}({:?})", did, CommaSep { iter: params, spaced: false })
+        }
+    }
+}
+impl Rule {
+    pub fn has_body(&self) -> bool {
+        !self.antecedents.is_empty() || !self.conditions.is_empty()
+    }
+    /// Writes the antecedents, followed by the conditions, e.g., `a(X), !b(X), X > 2`.
+    pub(crate) fn fmt_body(&self, f: &mut Formatter<'_>) -> FmtResult {
+        CommaSep { iter: &self.antecedents, spaced: true }.fmt(f)?;
+        for (i, condition) in self.conditions.iter().enumerate() {
+            if i > 0 || !self.antecedents.is_empty() {
+                write!(f, ", ")?;
             }
+            condition.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Compare { left, op, right, .. } => {
+                write!(f, "{:?} {} {:?}", left, op.symbol(), right)
+            }
+            Self::Builtin { sign, predicate, args, .. } => {
+                if sign == &Sign::Neg {
+                    write!(f, "!")?
+                }
+                write!(f, "{}({:?})", predicate.name(), CommaSep { iter: args, spaced: false })
+            }
+        }
+    }
+}
+impl Comparison {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
         }
     }
+    /// Whether this is `=` or `!=`, which compare values of any domain.
+    pub fn is_equality(self) -> bool {
+        matches!(self, Self::Eq | Self::Ne)
+    }
 }
+impl Arithmetic {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Rem => "%",
+        }
+    }
+    /// Operators of greater precedence bind more tightly.
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Add | Self::Sub => 0,
+            Self::Mul | Self::Div | Self::Rem => 1,
+        }
+    }
+}
+impl Function {
+    pub const ALL: [Self; 5] = [Self::Min, Self::Max, Self::Concat, Self::Length, Self::Lowercase];
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Concat => "concat",
+            Self::Length => "length",
+            Self::Lowercase => "lowercase",
+        }
+    }
+}
+impl Predicate {
+    pub const ALL: [Self; 4] = [Self::StartsWith, Self::EndsWith, Self::Contains, Self::Split];
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::StartsWith => "starts_with",
+            Self::EndsWith => "ends_with",
+            Self::Contains => "contains",
+            Self::Split => "split",
+        }
+    }
+}
+impl Aggregation {
+    pub const ALL: [Self; 4] = [Self::Count, Self::Sum, Self::Min, Self::Max];
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+impl Debug for Aggregate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let Self { aggregation, terms, literals, .. } = self;
+        let terms = CommaSep { iter: terms, spaced: false };
+        let literals = CommaSep { iter: literals, spaced: true };
+        write!(f, "{}{{{:?} : {:?}}}", aggregation.name(), terms, literals)
+    }
+}
+impl Debug for Expr {
+    /// Parenthesizes only where needed, given that operators associate to the left.
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Atom(ra) => ra.fmt(f),
+            Self::Arithmetic { op, args } => {
+                let precedence = |expr: &Expr| match expr {
+                    Self::Arithmetic { op, .. } => Some(op.precedence()),
+                    _ => None,
+                };
+                let [left, right] = args.as_ref();
+                if precedence(left).is_some_and(|p| p < op.precedence()) {
+                    write!(f, "({:?})", left)?;
+                } else {
+                    left.fmt(f)?;
+                }
+                write!(f, " {} ", op.symbol())?;
+                if precedence(right).is_some_and(|p| p <= op.precedence()) {
+                    write!(f, "({:?})", right)
+                } else {
+                    right.fmt(f)
+                }
+            }
+            Self::Call { function, args, .. } => {
+                write!(f, "{}({:?})", function.name(), CommaSep { iter: args, spaced: false })
+            }
+            Self::Aggregate(aggregate) => aggregate.fmt(f),
+        }
+    }
+}
+
 impl Debug for DomainId {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         write!(f, "{}", self.0)
     }
 }
+impl Debug for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
 impl Debug for VariableId {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         write!(f, "{}", self.0)
@@ -257,7 +596,25 @@ impl Debug for Constant {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         match self {
             Self::Int(c) => c.fmt(f),
-            Self::Str(c) => c.fmt(f),
+            Self::Str(c) => StringLiteral(c).fmt(f),
+        }
+    }
+}
+impl Debug for StringLiteral<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                '\0' => f.write_str("\\0")?,
+                c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+                c => f.write_char(c)?,
+            }
         }
+        f.write_char('"')
     }
 }
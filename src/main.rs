@@ -2,18 +2,51 @@ pub mod cli;
 pub mod lang;
 
 use cli::{
-    config::Config,
-    run::{run_check, stdin_to_string},
+    config::{Command, Config},
+    repl::run_repl,
+    run::{run_check, run_fmt, run_tests, stdin_to_string, ExitStatus, Failure},
 };
 
-use lang::*;
+use lang::{load::Sources, *};
+use std::path::Path;
 
 fn main() {
-    use std::io::Write;
-    let mut stdout = std::io::stdout().lock();
     let config = Config::from_sys_args();
-    let source = stdin_to_string().expect("bad stdin");
-    if let Err(e) = run_check(config, source, &mut stdout) {
-        let _ = writeln!(&mut stdout, "{}", e);
+    let status = match run(&config) {
+        Ok(status) => status,
+        Err(failure) => {
+            eprintln!("{}", failure.message);
+            failure.status
+        }
+    };
+    std::process::exit(status.code());
+}
+
+/// Runs the configured command, with requested output to stdout, and errors and warnings to stderr.
+fn run(config: &Config) -> Result<ExitStatus, Failure> {
+    let mut stdout = std::io::stdout().lock();
+    let mut stderr = std::io::stderr().lock();
+    if config.command == Command::Test {
+        return run_tests(config, &mut stdout).map(ExitStatus::of_tests);
+    }
+    if config.command == Command::Repl {
+        return Ok(run_repl(config, std::io::stdin().lock(), &mut stdout, &mut stderr));
+    }
+    let mut sources = Sources::default();
+    if config.input_paths.is_empty() {
+        sources.add("<stdin>".to_owned(), None, stdin_to_string().expect("bad stdin"));
+    }
+    for path in config.input_paths.iter() {
+        if path == "-" {
+            sources.add("<stdin>".to_owned(), None, stdin_to_string().expect("bad stdin"));
+        } else if let Err(e) = sources.read_file(Path::new(path)) {
+            let message = format!("error: cannot read `{}`: {}", path, e);
+            return Err(Failure::new(ExitStatus::LoadError, message));
+        }
+    }
+    match config.command {
+        Command::Fmt => run_fmt(&sources, &mut stdout).map(|()| ExitStatus::Success),
+        _ => run_check(config.clone(), &mut sources, &mut stdout, &mut stderr)
+            .map(|outcome| outcome.status(config)),
     }
 }
@@ -1,38 +1,720 @@
-use crate::cli::config::Config;
-use std::ffi::OsStr;
-use std::path::Path;
+use crate::{
+    cli::{
+        config::Config,
+        repl::run_repl,
+        run::{run_check, run_tests, ExitStatus},
+    },
+    load::Sources,
+};
 
-struct Null;
-impl std::io::Write for Null {
-    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
-        Ok(buf.len())
+/// Runs `program` as configured by the command line `args`, returning the exit status, the
+/// output, and the errors and warnings.
+fn run(args: &[&str], program: &str) -> (ExitStatus, String, String) {
+    let config = Config::from_args(args.iter().map(|&arg| arg.to_owned())).expect("valid args");
+    let mut sources = Sources::default();
+    sources.add("<test>".to_owned(), None, program.to_owned());
+    let [mut out, mut err] = [Vec::<u8>::default(), Vec::<u8>::default()];
+    let status = match run_check(config.clone(), &mut sources, &mut out, &mut err) {
+        Ok(outcome) => outcome.status(&config),
+        Err(failure) => {
+            err.extend(failure.message.bytes());
+            failure.status
+        }
+    };
+    let [out, err] = [out, err].map(|bytes| String::from_utf8(bytes).expect("utf-8 output"));
+    (status, out, err)
+}
+
+/// The truths of the denotation of `program`, as printed, e.g., `a(1)`.
+fn truths(program: &str) -> Vec<String> {
+    let (status, out, err) = run(&["run"], program);
+    assert_eq!(status, ExitStatus::Success, "{}", err);
+    let lines = out.lines().skip_while(|line| !line.ends_with("truths: {")).skip(1);
+    let lines = lines.take_while(|line| line.trim() != "},");
+    lines.map(|line| line.trim().trim_end_matches(',').to_owned()).collect()
+}
+
+#[test]
+fn examples() {
+    let mut config = Config::no_flags();
+    config.input_paths.push("./example_programs".to_owned());
+    let mut report = Vec::<u8>::default();
+    let failures = run_tests(&config, &mut report).expect("examples are found");
+    assert_eq!(failures, 0, "{}", String::from_utf8_lossy(&report));
+}
+
+#[test]
+fn semi_naive_closures() {
+    // left-linear, right-linear, and nonlinear rules infer the same transitive closure
+    let edges = [(1, 2), (2, 3), (3, 1), (3, 4), (4, 5), (6, 6), (7, 1)];
+    let mut program = "defn edge(int, int). left(int, int). right(int, int). both(int, int).
+        rule left(A, B) :- edge(A, B). left(A, C) :- left(A, B), edge(B, C).
+             right(A, B) :- edge(A, B). right(A, C) :- edge(A, B), right(B, C).
+             both(A, B) :- edge(A, B). both(A, C) :- both(A, B), both(B, C).\n"
+        .to_owned();
+    for (a, b) in edges {
+        program.push_str(&format!("rule edge({}, {}).\n", a, b));
     }
-    fn flush(&mut self) -> Result<(), std::io::Error> {
-        Ok(())
+    let mut closure: Vec<(i32, i32)> = edges.to_vec();
+    loop {
+        let mut longer: Vec<(i32, i32)> = closure
+            .iter()
+            .flat_map(|&(a, b)| {
+                edges.iter().filter(move |&&(c, _)| b == c).map(move |&(_, d)| (a, d))
+            })
+            .filter(|pair| !closure.contains(pair))
+            .collect();
+        if longer.is_empty() {
+            break;
+        }
+        longer.sort();
+        longer.dedup();
+        closure.extend(longer);
+    }
+    closure.sort();
+    let truths = truths(&program);
+    for relation in ["left", "right", "both"] {
+        let inferred: Vec<&str> = truths
+            .iter()
+            .filter_map(|truth| truth.strip_prefix(relation)?.strip_prefix('('))
+            .collect();
+        let expected: Vec<String> = closure.iter().map(|(a, b)| format!("{},{})", a, b)).collect();
+        assert_eq!(inferred, expected, "{}", relation);
     }
 }
 
-fn run_test(path: &Path) -> Result<(), ()> {
-    let contents = std::fs::read_to_string(path).map_err(drop)?;
-    let config = Config::no_flags();
-    crate::cli::run::run_check(config, contents, &mut Null).map(drop).map_err(drop)
+#[test]
+fn knowledge_indexes() {
+    use crate::lang::{
+        dynamics::{Atom, Knowledge},
+        Constant, DomainId,
+    };
+    let did = DomainId("trust".into());
+    let int = |i| Atom::Constant { c: Constant::Int(i) };
+    let trust = |a, b| Atom::Construct { did: did.clone(), args: vec![int(a), int(b)] };
+    let mut knowledge = Knowledge::default();
+    knowledge.insert(&did, trust(1, 2));
+    assert!(knowledge.atoms_with_argument(&did, 1, &int(2)).is_none());
+    knowledge.ensure_index(&did, 1);
+    knowledge.insert(&did, trust(3, 2));
+    knowledge.insert(&did, trust(3, 4));
+    assert!(!knowledge.insert(&did, trust(3, 2)));
+    let with_2 = knowledge.atoms_with_argument(&did, 1, &int(2)).expect("indexed");
+    assert_eq!(crate::lang::util::sorted_vec(with_2), [&trust(1, 2), &trust(3, 2)]);
+    // indexes are not cloned, but rebuilt on demand
+    let mut clone = knowledge.clone();
+    assert!(clone == knowledge && clone.atoms_with_argument(&did, 1, &int(2)).is_none());
+    clone.ensure_index(&did, 0);
+    assert_eq!(clone.atoms_with_argument(&did, 0, &int(3)).expect("indexed").count(), 2);
 }
 
-fn test_all(path: impl AsRef<Path>) {
-    let path = path.as_ref();
-    if path.extension() == Some(&OsStr::new("seaso")) {
-        let pass = run_test(path).is_ok();
-        let sign = if pass { "pass" } else { "FAIL" };
-        println!("{} {}", sign, path.display());
+#[test]
+fn join_orders() {
+    // each order of the antecedents infers what nested loops over the facts would
+    let [bigs, smalls] = [0..8, 0..4].map(|range| range.collect::<Vec<i64>>());
+    let links: Vec<(i64, i64)> = (0..10)
+        .flat_map(|a| (0..6).map(move |b| (a, b)))
+        .filter(|(a, b)| (a + 2 * b) % 3 == 0)
+        .collect();
+    let mut program =
+        "defn big(int). small(int). link(int, int). blocked(int).\nrule blocked(2).\n".to_owned();
+    for big in bigs.iter() {
+        program += &format!("rule big({}).\n", big);
     }
-    if let Ok(children) = std::fs::read_dir(path) {
-        for child in children {
-            test_all(child.unwrap().path())
+    for small in smalls.iter() {
+        program += &format!("rule small({}).\n", small);
+    }
+    for (a, b) in links.iter() {
+        program += &format!("rule link({}, {}).\n", a, b);
+    }
+    let orders = [
+        "big(A), small(B), link(A, B)",
+        "big(A), link(A, B), small(B)",
+        "small(B), big(A), link(A, B)",
+        "small(B), link(A, B), big(A)",
+        "link(A, B), big(A), small(B)",
+        "link(A, B), small(B), big(A)",
+    ];
+    for (i, order) in orders.iter().enumerate() {
+        program +=
+            &format!("defn x{}(int, int).\nrule x{}(A, B) :- {}, !blocked(B).\n", i, i, order);
+    }
+    let truths = truths(&program);
+    let mut expected = vec![];
+    for &a in bigs.iter() {
+        for &b in smalls.iter() {
+            if links.contains(&(a, b)) && b != 2 {
+                expected.push(format!("({},{})", a, b));
+            }
         }
     }
+    expected.sort();
+    assert!(!expected.is_empty());
+    for (i, order) in orders.iter().enumerate() {
+        let prefix = format!("x{}", i);
+        let mut inferred: Vec<_> =
+            truths.iter().filter_map(|truth| truth.strip_prefix(&prefix)).collect();
+        inferred.sort();
+        assert_eq!(inferred, expected, "{}", order);
+    }
+    // rather than enumerating the cross product of `big` and `small`, the join follows `link`
+    use crate::lang::{
+        plan::{JoinPlan, JoinStep},
+        AnnotatedRule, StatementKind,
+    };
+    let text = "x(A, B) :- big(A), small(B), link(A, B), !blocked(B)";
+    let Ok((_, StatementKind::Rule(rule))) = crate::lang::parse::rule(text) else { panic!() };
+    let plans = AnnotatedRule::new(Default::default(), rule).plans;
+    let steps = |plan: &JoinPlan| -> Vec<String> {
+        let steps = plan.steps.iter().map(|step| match step {
+            JoinStep::Match { antecedent_idx, lookup } => {
+                format!("{} {:?}", antecedent_idx, lookup)
+            }
+            JoinStep::Check { antecedent_idx } => format!("{} Check", antecedent_idx),
+            JoinStep::Evaluate { condition_idx } => format!("{} Evaluate", condition_idx),
+        });
+        steps.collect()
+    };
+    assert_eq!(steps(&plans.all), ["0 Scan", "2 Argument(0)", "3 Check", "1 Whole"]);
+    // semi-naive rounds start with the antecedent ranging over the newest atoms
+    assert_eq!(steps(&plans.delta[&1]), ["1 Scan", "3 Check", "2 Argument(1)", "0 Whole"]);
 }
 
 #[test]
-fn examples() {
-    test_all("./example_programs")
+fn spans() {
+    use crate::lang::{parse, StatementKind};
+    let source = "defn a(int).\n\nrule a(1).\n   a(X) :-\n     a(X), !b(X:int).";
+    let (_, program) = parse::program(source).expect("parses");
+    let text = |span: crate::lang::Span| &source[span.start as usize..span.end as usize];
+    let statement = &program.anon_mod_statements[2];
+    let StatementKind::Rule(rule) = &statement.kind else { panic!("a rule") };
+    assert_eq!(text(statement.span), "a(X) :-\n     a(X), !b(X:int)");
+    assert_eq!(rule.span, statement.span);
+    assert_eq!((statement.span.line, statement.span.col), (4, 4));
+    let [positive, negative] = [0, 1].map(|i| rule.antecedents[i].ra.span());
+    assert_eq!([text(positive), text(negative)], ["a(X)", "b(X:int)"]);
+    assert_eq!((negative.line, negative.col), (5, 13));
+}
+
+#[test]
+fn diagnostics() {
+    let (status, _, err) = run(&["check"], "defn a(int).\nrule a(\"x\").\n");
+    assert_eq!(status, ExitStatus::StaticError);
+    let expected = [
+        "error[E0006]: mistyped argument of `a`",
+        " --> <test>:2:8",
+        "  |",
+        "2 | rule a(\"x\").",
+        "  |        ^^^ expected `int`, found `str`",
+        "  |",
+        "  = note: in rule `a(\"x\")`",
+        "",
+    ];
+    assert_eq!(err, expected.join("\n"));
+}
+
+#[test]
+fn imports() {
+    use std::path::Path;
+    let dir = Path::new("./example_programs/features_by_example");
+    let mut sources = Sources::default();
+    // imports are resolved relative to the importing file, rather than the working directory
+    for path in
+        ["015_import.seaso", "imported/shop.seaso", "../features_by_example/015_import.seaso"]
+    {
+        sources.read_file(&dir.join(path)).expect("readable");
+    }
+    // each file is loaded once, however it is named
+    assert_eq!(sources.iter().count(), 2);
+    let [mut out, mut err] = [Vec::<u8>::default(), Vec::<u8>::default()];
+    let outcome = run_check(Config::no_flags(), &mut sources, &mut out, &mut err);
+    let err = String::from_utf8(err).expect("utf-8 output");
+    let denotation = outcome.map_err(|failure| failure.message).expect("loads").denotation;
+    assert_eq!(err, "");
+    let truths = format!("{:?}", denotation.expect("run").bare().truths);
+    assert!(truths.contains("buys(item(\"apple\"))"), "{}", truths);
+}
+
+#[test]
+fn json_snapshot() {
+    let program = r#"defn item(str). sold(item).
+rule item("a\"b"). sold(item(S)) :- item(S), !tag(1). emit sold.
+?- sold(I)."#;
+    let (status, out, _) = run(&["--format", "json"], program);
+    assert_eq!(status, ExitStatus::Success);
+    // the output, with its lines trimmed and joined
+    let compact: String = out.lines().map(str::trim).collect();
+    let expected = [
+        r#"{"truths": {"item": [{"domain": "item","args": [{"str": "a\"b"}]}],"sold": [{"domain": "sold","args": [{"domain": "item","args": [{"str": "a\"b"}]}]}]},"#,
+        r#""unknowns": {},"#,
+        r#""emissions": {"sold": [{"domain": "sold","args": [{"domain": "item","args": [{"str": "a\"b"}]}]}]},"#,
+        r#""warnings": {"seal_breaks": [],"used_undeclared": [{"domain": "tag","span": {"file": "<test>","line": 2,"col": 47,"start": 74,"end": 80}}],"depended_undefined": [],"unbounded_values": []},"#,
+        r#""domain_dependencies": {"edges": [{"from": "item","to": "sold","sign": "pos"},{"from": "tag","to": "sold","sign": "neg"}],"sccs": [{"domains": ["tag"],"negative": false},{"domains": ["item"],"negative": false},{"domains": ["sold"],"negative": false}]},"#,
+        r#""stratification": {"stratified": true,"strata": [["item","tag"],["sold"]]},"#,
+        r#""queries": [{"query": "query sold(I)","answers": [{"bindings": {"I": {"domain": "item","args": [{"str": "a\"b"}]}},"truth_value": "true"}]}]}"#,
+    ]
+    .concat();
+    assert_eq!(compact, expected);
+}
+
+#[test]
+fn max_atoms() {
+    let program = "defn n(int). edge(n, n). path(n, n).
+        rule n(1). n(2). n(3). n(4). edge(n(1), n(2)). edge(n(2), n(3)). edge(n(3), n(1)).
+             edge(n(3), n(4)). path(A, B) :- edge(A, B). path(A, C) :- path(A, B), edge(B, C).";
+    assert_eq!(truths(program).len(), 20);
+    assert_eq!(run(&["--max-atoms", "20"], program).0, ExitStatus::Success);
+    let (status, _, err) = run(&["--max-atoms", "19"], program);
+    assert_eq!(status, ExitStatus::MaxAtomsExceeded, "{}", err);
+}
+
+#[test]
+fn json_warnings() {
+    let program = "rule t(1).";
+    let (status, out, err) = run(&["check", "--format", "json"], program);
+    assert_eq!((status, &err[..]), (ExitStatus::Success, ""));
+    assert!(
+        out.starts_with("{\n  \"warnings\": {") && out.contains("\"domain\": \"t\""),
+        "{}",
+        out
+    );
+    let (status, out, err) = run(&["explain", "--format", "json"], program);
+    assert_eq!((status, &err[..]), (ExitStatus::Success, ""));
+    assert!(
+        out.starts_with("{\n  \"inferences\": [") && out.contains("\"warnings\": {"),
+        "{}",
+        out
+    );
+    let (status, _, err) = run(&["check", "--format", "json", "--dot", "parts"], program);
+    assert_eq!(status, ExitStatus::Success);
+    assert!(err.starts_with("warning[W0002]"), "{}", err);
+}
+
+#[test]
+fn test_command() {
+    let dir = std::env::temp_dir().join(format!("seaso-test-command-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("empty")).expect("writable");
+    std::fs::write(dir.join("notes.txt"), "not seaso").expect("writable");
+    let run_tests_at = |paths: &[&str]| {
+        let mut config = Config::no_flags();
+        config.input_paths.extend(paths.iter().map(|path| dir.join(path).display().to_string()));
+        run_tests(&config, &mut std::io::sink()).map_err(|failure| failure.status)
+    };
+    let results =
+        [run_tests_at(&["missing"]), run_tests_at(&["notes.txt"]), run_tests_at(&["empty"])];
+    std::fs::remove_dir_all(&dir).expect("removable");
+    assert_eq!(results, [Ok(1), Ok(1), Err(ExitStatus::LoadError)]);
+}
+
+#[test]
+fn exit_statuses() {
+    let cases = [
+        (&[][..], "rule a(", ExitStatus::LoadError),
+        (&["--query", "a("][..], "rule a.", ExitStatus::UsageError),
+        (&[][..], "defn a(int). rule a(\"x\").", ExitStatus::StaticError),
+        (&[][..], "defn n(n). rule n(n(X)) :- n(X).", ExitStatus::UnboundedDomainCycle),
+        (&["--max-atoms", "1"][..], "rule a. b. c.", ExitStatus::MaxAtomsExceeded),
+        (&["--fail-on-warnings"][..], "rule t(1).", ExitStatus::Warnings),
+        (&["--fail-on-emissions"][..], "rule a. emit a.", ExitStatus::Emissions),
+    ];
+    for (code, (args, program, expected)) in (1..).zip(cases) {
+        let (status, _, err) = run(args, program);
+        assert_eq!(status, expected, "{}", err);
+        assert_eq!(status.code(), code);
+    }
+    let dir = std::env::temp_dir().join(format!("seaso-exit-statuses-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("writable");
+    std::fs::write(dir.join("bad.seaso"), "rule a(").expect("writable");
+    let mut config = Config::no_flags();
+    config.input_paths.push(dir.display().to_string());
+    let status = ExitStatus::of_tests(run_tests(&config, &mut std::io::sink()).expect("found"));
+    std::fs::remove_dir_all(&dir).expect("removable");
+    assert_eq!(status, ExitStatus::TestFailures);
+    assert_eq!(status.code(), 8);
+}
+
+#[test]
+fn lint_levels() {
+    let program = "part a { rule 1. } part b { seal int. }";
+    let cases = [
+        (&[][..], ExitStatus::Success, "warning[W0003]"),
+        (&["--deny", "seal-breaks"][..], ExitStatus::StaticError, "error[W0003]"),
+        (&["--allow", "seal-breaks"][..], ExitStatus::Success, ""),
+        (&["--strict"][..], ExitStatus::StaticError, "error[W0003]"),
+        (&["--strict", "--warn", "seal-breaks"][..], ExitStatus::Success, "warning[W0003]"),
+        (&["--deny-warnings", "--allow", "seal-breaks"][..], ExitStatus::Success, ""),
+    ];
+    for (args, expected, prefix) in cases {
+        let args = [&["check"][..], args].concat();
+        let (status, _, err) = run(&args, program);
+        assert_eq!(status, expected, "{:?}: {}", args, err);
+        assert!(err.starts_with(prefix) && err.is_empty() == prefix.is_empty(), "{}", err);
+        let denied = status == ExitStatus::StaticError;
+        assert_eq!(err.contains("error: aborting due to 1 denied warning(s)"), denied, "{}", err);
+    }
+}
+
+#[test]
+fn repl_script() {
+    let input = "decl a.\nrule a(\"{\").\nemit a.\n:undo\n:truths\n:quit\nrule a(\"ignored\").\n";
+    let [mut out, mut err] = [Vec::<u8>::default(), Vec::<u8>::default()];
+    run_repl(&Config::no_flags(), input.as_bytes(), &mut out, &mut err);
+    let out = String::from_utf8(out).expect("utf-8 output");
+    let expected = [
+        "no change to the denotation",
+        "+ truth a(\"{\")",
+        "+ emission a(\"{\")",
+        "- emission a(\"{\")",
+        "{",
+        "    a(\"{\"),",
+        "}",
+        // the prompt at which `:quit` is entered
+        "",
+    ];
+    let lines: Vec<&str> = out.lines().map(|line| line.trim_start_matches("seaso> ")).collect();
+    assert_eq!(lines[1..], expected, "{}", out);
+    assert!(err.is_empty(), "{}", String::from_utf8_lossy(&err));
+}
+
+#[test]
+fn queries() {
+    let program = "defn p(int). q(int). r(int).
+        rule p(1). p(2). p(3). q(X) :- p(X), !r(X). r(2) :- !q(2).
+        ?- q(X). query p(X), !q(X). ?- q(4).";
+    let (status, out, err) = run(&["--no-deno"], program);
+    assert_eq!(status, ExitStatus::Success, "{}", err);
+    let expected = [
+        "query q(X):",
+        "    X = 1 (true)",
+        "    X = 2 (unknown)",
+        "    X = 3 (true)",
+        "query p(X), !q(X):",
+        "    X = 2 (unknown)",
+        "query q(4):",
+        "    no answers",
+        "",
+    ];
+    assert_eq!(out, expected.join("\n"));
+}
+
+#[test]
+fn why_not_depth() {
+    let program = "defn a(int). b(int). c(int). d(int).
+        rule d(X) :- c(X). c(X) :- b(X). b(X) :- a(X).";
+    let why_not = |depth: &str| {
+        let (status, out, err) = run(&["explain", "--query", "d(1)", "--depth", depth], program);
+        assert_eq!(status, ExitStatus::Success, "{}", err);
+        out
+    };
+    let shallow = [
+        "d(1) is false",
+        "  rule `d(1) :- c(1)` fails, as",
+        "    `c(1)` is false",
+        "      c(1) is false",
+        "        rule `c(1) :- b(1)` fails, as",
+        "          `b(1)` is false",
+        "",
+    ];
+    assert_eq!(why_not("0"), shallow[..3].join("\n") + "\n");
+    assert_eq!(why_not("1"), shallow.join("\n"));
+    let deep = why_not("3");
+    assert!(deep.ends_with("                    no rule has a consequent matching it\n"));
+    assert_eq!(why_not("9"), deep);
+}
+
+#[test]
+fn why_unknown_subconsequent() {
+    let program = "defn a(int). defn b(a). rule b(a(1)) :- !b(a(1)).";
+    let (status, out, _) = run(&["explain", "--query", "a(1)", "--sub"], program);
+    assert_eq!(status, ExitStatus::Success);
+    assert!(out.starts_with("a(1) is unknown, as it depends on a cycle through negation:\n"));
+    assert!(out.contains("a(1) depends on !b(a(1)), by rule `b(a(1)) :- !b(a(1))`"));
+}
+
+#[test]
+fn proofs() {
+    let program = "decl x. y. z. n. rule x. y :- x. z :- y, x, !n.";
+    let (status, out, err) = run(&["explain", "--query", "z"], program);
+    assert_eq!(status, ExitStatus::Success, "{}", err);
+    let expected = [
+        "z, by `z :- y, x, !n`",
+        "  y, by `y :- x`",
+        "    x, a fact",
+        "  x, proven above",
+        "  !n, as it has no derivation",
+        "",
+    ];
+    assert_eq!(out, expected.join("\n"));
+    // `x` is shared by both rules, so the proof graph holds it once.
+    let (status, out, err) = run(&["explain", "--query", "z", "--format", "json"], program);
+    assert_eq!(status, ExitStatus::Success, "{}", err);
+    assert_eq!(out.matches("\"domain\": \"x\"").count(), 1);
+    assert_eq!(out.matches("\"step\": \"underivable\"").count(), 1);
+    assert_eq!(out.matches("\"sign\": \"neg\"").count(), 1);
+}
+
+#[test]
+fn parts_dot() {
+    let program = "part a { rule 1. }\npart b: c { seal int. }\npart c {}\nrule 2.";
+    let (status, out, err) = run(&["--dot", "parts", "--allow", "seal-breaks"], program);
+    assert_eq!(status, ExitStatus::Success, "{}", err);
+    let expected = [
+        "digraph parts {",
+        "  \"a\";",
+        "  \"b\" [label=\"b\\nseals int\", style=bold];",
+        "  \"c\";",
+        "  \"line 4\";",
+        "  \"b\" -> \"c\";",
+        "  \"a\" -> \"b\" [color=red, fontcolor=red, label=\"int\"];",
+        "  \"line 4\" -> \"b\" [color=red, fontcolor=red, label=\"int\"];",
+        "}",
+        "",
+    ];
+    assert_eq!(out, expected.join("\n"));
+}
+
+#[test]
+fn domain_sccs() {
+    let program = "defn a(int). b(int). c(int). d(int). e(int).
+        rule a(1). b(X) :- a(X), c(X). c(X) :- b(X). d(X) :- c(X), !e(X). e(X) :- a(X).";
+    let (status, out, err) = run(&["check", "--dot", "domains"], program);
+    assert_eq!(status, ExitStatus::Success, "{}", err);
+    // each SCC precedes those depending on it, with the cycle of `b` and `c` clustered
+    let nodes: Vec<&str> = out.lines().filter(|line| !line.contains("->")).collect();
+    let expected = [
+        "digraph domains {",
+        "  \"a\" [color=black];",
+        "  \"e\" [color=black];",
+        "  subgraph cluster_2 {",
+        "    color=black;",
+        "    \"b\" [color=black];",
+        "    \"c\" [color=black];",
+        "  }",
+        "  \"d\" [color=black];",
+        "}",
+    ];
+    assert_eq!(nodes, expected);
+    assert!(out.contains("\"c\" -> \"b\";\n") && out.contains("\"e\" -> \"d\" [style=dashed];\n"));
+    let (_, out, _) = run(&["check", "--strata"], program);
+    assert_eq!(out, "stratified:\n  0: a, b, c, e\n  1: d\n");
+}
+
+#[test]
+fn stratified_denotations() {
+    use crate::{preprocessing, EqClasses, ExecutableConfig};
+    use std::path::Path;
+    fn rec(path: &Path, compared: &mut usize) {
+        if path.extension() == Some(std::ffi::OsStr::new("seaso")) {
+            let mut sources = Sources::default();
+            sources.read_file(path).expect("readable");
+            let Ok(mut program) = sources.load() else { return };
+            preprocessing::normalize_domain_id_formatting(&mut program, false);
+            let eq_classes = EqClasses::new(&program);
+            eq_classes.normalize_equal_domain_ids(&mut program);
+            preprocessing::deanonymize_variables(&mut program);
+            let Ok(ep) = program.executable(ExecutableConfig::default()) else { return };
+            if ep.domain_dependency_graph().strata().is_ok() {
+                let [stratified, alternating] = [ep.denotation(), ep.alternating_denotation()]
+                    .map(|dr| format!("{:?}", dr.expect("bounded").denotation.bare()));
+                assert_eq!(stratified, alternating, "{}", path.display());
+                *compared += 1;
+            }
+        }
+        for child in std::fs::read_dir(path).into_iter().flatten() {
+            rec(&child.expect("readable").path(), compared);
+        }
+    }
+    let mut compared = 0;
+    rec(Path::new("./example_programs"), &mut compared);
+    assert!(compared > 0);
+}
+
+#[test]
+fn seal_chains() {
+    // `a` uses `b` through a chain of parts, so `b` can modify what `a` seals
+    let program = "part a: d { seal int. } part d: c {} part c: b {} part b { rule 1. }
+        part e { rule 2. }";
+    let (status, _, err) = run(&["check"], program);
+    assert_eq!(status, ExitStatus::Success);
+    let breaks: Vec<&str> = err.lines().filter(|line| line.starts_with("warning")).collect();
+    assert_eq!(breaks, ["warning[W0003]: part \"e\" breaks the seal on `int`"]);
+}
+
+#[test]
+fn digraph() {
+    use crate::lang::util::Digraph;
+    let mut digraph = Digraph::default();
+    for edge in [[1, 2], [2, 3], [3, 2], [3, 4], [5, 5]] {
+        digraph.insert_edge(edge);
+    }
+    digraph.insert_vert(0);
+    let sccs = digraph.sccs();
+    let position = |x: i32| sccs.iter().position(|scc| scc.contains(&&x)).unwrap();
+    assert!(sccs.iter().any(|scc| scc == &[&2, &3]));
+    assert!(position(1) < position(2) && position(2) < position(4));
+    assert!(digraph.reaches(&1, &4) && !digraph.reaches(&4, &1) && !digraph.reaches(&1, &1));
+    assert_eq!(digraph.shortest_path(&1, &4), Some(vec![&1, &2, &3, &4]));
+    assert_eq!(digraph.find_cycle(), Some(vec![&2, &3, &2]));
+    assert_eq!(digraph.topological_order(), Err(vec![&2, &3, &2]));
+    assert_eq!(digraph.reachable(&1).into_iter().collect::<Vec<_>>(), [&2, &3, &4]);
+    // long paths are searched without recursion
+    let mut chain = Digraph::default();
+    for x in 0..100_000 {
+        chain.insert_edge([x, x + 1]);
+    }
+    assert_eq!(chain.sccs().len(), 100_001);
+    assert!(chain.topological_order().is_ok());
+}
+
+#[test]
+fn unbounded_cycle_path() {
+    let (status, _, err) = run(&["check"], "defn f(g). g(f).\nrule f(G) :- G.\ng(F) :- F.\n");
+    assert_eq!(status, ExitStatus::UnboundedDomainCycle);
+    let expected = [
+        "error[E0011]: termination uncertain due to unbounded domain cycle through `f`",
+        " --> <test>:2:6",
+        "  |",
+        "2 | rule f(G) :- G.",
+        "  |      ^^^^ `f` contains `g`",
+        "3 | g(F) :- F.",
+        "  | ---- `g` contains `f`",
+        "  |",
+        "  = note: the cycle is f -> g -> f",
+        "  = note: rules may infer ever larger atoms along this cycle",
+        "",
+    ];
+    assert_eq!(err, expected.join("\n"));
+}
+
+#[test]
+fn block_comments() {
+    use crate::preprocessing::comments_removed;
+    let program = "<a> rule a :- <b> b(X), <c> X < 2, count{ Y : b(Y) } <= 3, c.";
+    let expected = "    rule a :-     b(X),     X < 2, count{ Y : b(Y) } <= 3, c.";
+    assert_eq!(comments_removed(program.to_owned()), expected);
+    let program = "defn b(int). rule b(1). b(4). a :- <b> b(X), <under 2> X < 2.";
+    assert!(run(&["run"], program).1.contains("        a,\n"));
+}
+
+#[test]
+fn unbounded_values() {
+    let unbounded = "defn a(int). rule a(0). rule a(X) :- a(Y), X = Y + 1.";
+    assert_eq!(run(&["check", "--fail-on-warnings"], unbounded).0, ExitStatus::Warnings);
+    assert_eq!(run(&["check", "--strict"], unbounded).0, ExitStatus::UnboundedDomainCycle);
+    let copied = "defn a(int). rule a(0). rule a(X) :- a(Y), Z = Y + 1, X = Z.";
+    assert_eq!(run(&["check", "--strict"], copied).0, ExitStatus::UnboundedDomainCycle);
+    let bounded = "defn a(int). b(int). rule a(0). b(X) :- a(Y), X = Y + 1. a(X) :- a(Y), X = Y.";
+    assert_eq!(run(&["check", "--strict"], bounded).0, ExitStatus::Success);
+}
+
+#[test]
+fn undefined_arithmetic() {
+    // conditions with undefined values do not hold, even with `!=`
+    let program = "defn n(int). quotient(int). remainder(int). negated(int). doubled(int).
+        rule n(0). n(-1). n(-9223372036854775808).
+             quotient(X) :- n(X), Y = X / -1, 1 / X <= 1.
+             remainder(X) :- n(X), Y = X % -1, 1 % X >= 0.
+             negated(X) :- n(X), 1 / 0 != X.
+             doubled(X) :- n(X), Y = X + X.";
+    let truths = truths(program);
+    let truths: Vec<&str> =
+        truths.iter().map(String::as_str).filter(|truth| !truth.starts_with("n(")).collect();
+    assert_eq!(truths, ["doubled(-1)", "doubled(0)", "quotient(-1)", "remainder(-1)"]);
+}
+
+#[test]
+fn split_empty_separator() {
+    // no pieces are separated by `""`, and whether one is is undefined, failing even if negated
+    let program = r#"defn s(str). piece(str). checked(str). negated(str).
+        rule s("a/b"). s("").
+             piece(P) :- s(S), split(S, "", P).
+             checked(S) :- s(S), split(S, "", S).
+             negated(S) :- s(S), !split(S, "", "a").
+             piece(P) :- s(S), split(S, "/", P)."#;
+    // only splitting by `"/"` infers pieces, where `""` is the one piece of `""`
+    let expected = [r#"piece("")"#, r#"piece("a")"#, r#"piece("b")"#, r#"s("")"#, r#"s("a/b")"#];
+    assert_eq!(truths(program), expected);
+}
+
+#[test]
+fn unstratified_aggregate() {
+    let program = "defn p(int). rule p(0). p(N) :- N = count{ X : p(X) }.";
+    let (status, _, err) = run(&["check"], program);
+    assert_eq!(status, ExitStatus::StaticError);
+    assert!(err.contains("error[E0019]: aggregate over `p` depends on its own result"), "{}", err);
+    assert!(err.contains("note: due to the cycle: `p` aggregates over `p`\n"), "{}", err);
+}
+
+#[test]
+fn aggregates() {
+    let program = "defn e(int, int). none(int). result(str, int).
+        rule e(1, 1). e(1, 2). e(2, 2).
+             result(\"count x\", N) :- N = count{ X : e(X, Y) }.
+             result(\"count x y\", N) :- N = count{ X, Y : e(X, Y) }.
+             result(\"sum y\", N) :- N = sum{ Y : e(X, Y) }.
+             result(\"sum x y\", N) :- N = sum{ Y, X : e(X, Y) }.
+             result(\"count none\", N) :- N = count{ X : none(X) }.
+             result(\"sum none\", N) :- N = sum{ X : none(X) }.
+             result(\"min none\", N) :- N = min{ X : none(X) }.
+             result(\"max none\", N) :- N = max{ X : none(X) }.
+             result(\"max none\", 0) :- max{ X : none(X) } != 1.";
+    let truths = truths(program);
+    let results: Vec<&str> =
+        truths.iter().map(String::as_str).filter(|truth| truth.starts_with("result")).collect();
+    // tuples are distinct, e.g., `sum{ Y : ... }` adds 2 only once, and `min` and `max` are
+    // undefined over no tuples, failing their conditions
+    let expected = [
+        r#"result("count none",0)"#,
+        r#"result("count x",2)"#,
+        r#"result("count x y",3)"#,
+        r#"result("sum none",0)"#,
+        r#"result("sum x y",5)"#,
+        r#"result("sum y",3)"#,
+    ];
+    assert_eq!(results, expected);
+}
+
+#[test]
+fn equalities() {
+    let program = "defn p(int). q(int, int). r(int). s(int).
+        rule p(1). p(2). p(3).
+             q(X, Y) :- p(X), p(Y), X != Y. r(Y) :- p(X), Y = X. s(X) :- p(X), X = 2.";
+    let truths = truths(program);
+    let count = |prefix: &str| truths.iter().filter(|truth| truth.starts_with(prefix)).count();
+    // `=` binds `Y` in `r`, and filters in `s`, and neither stores atoms of its own
+    assert_eq!([count("p("), count("q("), count("r("), count("s(")], [3, 6, 3, 1]);
+    assert_eq!(truths.len(), 13);
+    assert!(truths.contains(&"s(2)".to_owned()));
+    let mistyped = "defn p(int). rule p(X) :- p(X), X = \"a\".";
+    assert_eq!(run(&["check"], mistyped).0, ExitStatus::StaticError);
+}
+
+#[test]
+fn string_literals() {
+    use crate::{parse::string, Constant};
+    let parsed = |literal: &str| match string(literal) {
+        Ok(("", s)) => Some(s),
+        _ => None,
+    };
+    let cases = [
+        (r#""""#, ""),
+        (r#""say \"hi\"""#, "say \"hi\""),
+        (r#""a\\b""#, "a\\b"),
+        (r#""\n\r\t\0""#, "\n\r\t\0"),
+        (r#""\u{A}\u{e9}\u{10FFFF}""#, "\n\u{e9}\u{10FFFF}"),
+        (r#"r"C:\files""#, "C:\\files"),
+        (r##"r#"{"a": "b"}"#"##, r#"{"a": "b"}"#),
+        (r###"r##"a "# b"##"###, r##"a "# b"##),
+    ];
+    for (literal, expected) in cases {
+        assert_eq!(parsed(literal).as_deref(), Some(expected), "{}", literal);
+        // printing escapes the string such that it reads back the same
+        let printed = format!("{:?}", Constant::Str(expected.to_owned()));
+        assert_eq!(parsed(&printed).as_deref(), Some(expected), "{}", printed);
+    }
+    assert_eq!(format!("{:?}", Constant::Str("\"\\\n\u{1}".to_owned())), r#""\"\\\n\u{1}""#);
+    let invalid = [r#""\u{D800}""#, r#""\u{0000041}""#, r#""\u{}""#, r#""\q""#, r#""open"#];
+    for literal in invalid.into_iter().chain([r#"r#"open""#, r#"r"open"#]) {
+        assert_eq!(parsed(literal), None, "{}", literal);
+    }
 }